@@ -1,9 +1,10 @@
 use defi_wallet::{
     core::App,
-    wallet::{WalletService, ChainType},
+    wallet::{WalletService, WalletFilter, ChainType},
 };
 use anyhow::Result;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -16,6 +17,9 @@ async fn main() -> Result<()> {
     // Create wallet service
     let wallet_service = WalletService::new(app.clone()).await?;
     
+    // Unlock with the passphrase from `wallet.encryption_key_source`
+    wallet_service.unlock_from_config(Duration::from_secs(300)).await?;
+    
     // Create Ethereum wallet
     let eth_wallet = wallet_service.create_wallet(ChainType::Ethereum).await?;
     println!("Created Ethereum wallet: {}", eth_wallet.address);
@@ -25,7 +29,7 @@ async fn main() -> Result<()> {
     println!("Created Solana wallet: {}", sol_wallet.address);
     
     // List all wallets
-    let wallets = wallet_service.list_wallets().await;
+    let wallets = wallet_service.list_wallets(&WalletFilter::default()).await;
     println!("\nAll wallets:");
     for wallet in wallets {
         println!("- {} ({:?})", wallet.address, wallet.chain_type);
@@ -14,6 +14,9 @@ async fn main() -> Result<()> {
     let app = Arc::new(App::new().await?);
     let defi_service = DeFiService::new(app).await?;
     
+    // Wallet executing the swaps; its spending policy applies
+    let wallet_address = "0x52908400098527886E0F7030069857D2E4169EE7".to_string();
+    
     // Define common tokens
     let eth_token = TokenInfo {
         address: "0xEeeeeEeeeEeEeeEeEeEeeEEEeeeeEeeeeeeeEEeE".to_string(),
@@ -31,6 +34,7 @@ async fn main() -> Result<()> {
     
     // Example 1: ETH to USDC on Uniswap V2
     let uniswap_v2_swap = SwapRequest {
+        from: wallet_address.clone(),
         from_token: eth_token.clone(),
         to_token: usdc_token.clone(),
         amount: 1.0,
@@ -47,6 +51,7 @@ async fn main() -> Result<()> {
     
     // Example 2: ETH to USDC on Uniswap V3
     let uniswap_v3_swap = SwapRequest {
+        from: wallet_address.clone(),
         from_token: eth_token.clone(),
         to_token: usdc_token.clone(),
         amount: 1.0,
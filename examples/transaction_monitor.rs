@@ -1,6 +1,7 @@
 use defi_wallet::{
     core::App,
     blockchain::{BlockchainService, ChainType, TransactionStatus, TransactionRequest},
+    wallet::WalletService,
 };
 use anyhow::Result;
 use std::sync::Arc;
@@ -13,7 +14,9 @@ async fn main() -> Result<()> {
     
     // Initialize the application
     let app = Arc::new(App::new().await?);
-    let blockchain_service = BlockchainService::new(app).await?;
+    let blockchain_service = BlockchainService::new(app.clone()).await?;
+    let wallet_service = WalletService::new(app).await?;
+    wallet_service.unlock_from_config(Duration::from_secs(300)).await?;
     
     // Example: Send a transaction
     let tx_request = TransactionRequest {
@@ -23,10 +26,17 @@ async fn main() -> Result<()> {
         chain_type: ChainType::Ethereum,
         gas_limit: Some(21000),
         gas_price: Some(20),
+        data: None,
+        compute_unit_limit: None,
+        compute_unit_price: None,
+        nonce_account: None,
+        nonce_authority: None,
+        fee_rate: None,
     };
     
     println!("Sending transaction...");
-    let tx_hash = blockchain_service.send_transaction(tx_request).await?;
+    let signer = wallet_service.signer(&tx_request.from).await?;
+    let tx_hash = blockchain_service.send_transaction(&tx_request, signer.as_ref()).await?;
     println!("Transaction sent! Hash: {}", tx_hash);
     
     // Monitor transaction status
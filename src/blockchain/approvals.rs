@@ -0,0 +1,535 @@
+use super::{BlockchainService, TransactionRequest};
+use crate::wallet::{normalize_address, signer::Signer, signing::verify_signature};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use ethers::types::Signature;
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyPool;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProposalStatus {
+    /// Waiting for approvals.
+    Pending,
+    /// Quorum reached; sent by `execute_proposal`.
+    Approved,
+    /// Being signed and broadcast.
+    Executing,
+    Executed { tx_hash: String },
+    /// Sending failed. This is final, as the transaction may still have been
+    /// broadcast; propose it again to retry.
+    Failed { error: String },
+    Rejected { by: String, reason: String },
+    Expired,
+}
+
+/// A transfer held back by the sender's `ApprovalRule` until `quorum` of
+/// `approvers` approve it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    pub id: String,
+    pub request: TransactionRequest,
+    pub approvers: Vec<String>,
+    pub quorum: usize,
+    /// Approvers who have approved so far.
+    pub approvals: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub status: ProposalStatus,
+}
+
+impl Proposal {
+    /// The text an approver signs (EIP-191) to approve. It spells out the
+    /// transfer so approvers see what they sign in their own wallet.
+    pub fn approval_message(&self) -> String {
+        format!("Approve transaction proposal {}\n{}", self.id, self.details())
+    }
+
+    /// The text an approver signs (EIP-191) to reject with `reason`.
+    pub fn rejection_message(&self, reason: &str) -> String {
+        format!("Reject transaction proposal {}\n{}\nReason: {}", self.id, self.details(), reason)
+    }
+
+    fn details(&self) -> String {
+        let request = &self.request;
+        let mut details = format!(
+            "Chain: {}\nFrom: {}\nTo: {}\nAmount: {}\n",
+            request.chain_type, request.from, request.to, request.amount
+        );
+        if let Some(data) = &request.data {
+            details.push_str(&format!("Data: 0x{}\n", hex::encode(data)));
+        }
+        // Fees are part of what is approved; unset ones are estimated on execution
+        let fees = [
+            ("Gas limit", request.gas_limit),
+            ("Gas price", request.gas_price),
+            ("Fee rate", request.fee_rate),
+            ("Compute unit limit", request.compute_unit_limit.map(u64::from)),
+            ("Compute unit price", request.compute_unit_price),
+        ];
+        for (name, value) in fees {
+            if let Some(value) = value {
+                details.push_str(&format!("{}: {}\n", name, value));
+            }
+        }
+        if let Some(nonce_account) = &request.nonce_account {
+            details.push_str(&format!("Nonce account: {}\n", nonce_account));
+        }
+        details.push_str(&format!(
+            "Expires: {}",
+            self.expires_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+        details
+    }
+
+    fn approver(&self, address: &str) -> Result<String> {
+        self.approvers
+            .iter()
+            .find(|approver| approver.eq_ignore_ascii_case(address))
+            .cloned()
+            .ok_or_else(|| anyhow!("{} is not an approver of proposal {}", address, self.id))
+    }
+
+    /// Marks the proposal expired if it is still waiting past its deadline.
+    fn expire(&mut self, now: DateTime<Utc>) {
+        if self.status == ProposalStatus::Pending && now >= self.expires_at {
+            self.status = ProposalStatus::Expired;
+        }
+    }
+}
+
+/// Proposals stored as JSON documents alongside the wallets in the wallet
+/// database, so they survive restarts.
+#[derive(Clone)]
+pub(crate) struct ProposalStore {
+    pool: AnyPool,
+}
+
+impl ProposalStore {
+    pub(crate) async fn new(pool: AnyPool) -> Result<Self> {
+        sqlx::query("CREATE TABLE IF NOT EXISTS proposals (id TEXT PRIMARY KEY, data TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn insert(&self, proposal: &Proposal) -> Result<()> {
+        sqlx::query("INSERT INTO proposals (id, data) VALUES ($1, $2)")
+            .bind(&proposal.id)
+            .bind(serde_json::to_string(proposal)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Loads proposal `id`, marking it expired if its deadline has passed.
+    async fn get(&self, id: &str) -> Result<Option<Proposal>> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT data FROM proposals WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let mut proposal: Option<Proposal> = row.map(|(data,)| serde_json::from_str(&data)).transpose()?;
+        if let Some(proposal) = &mut proposal {
+            proposal.expire(Utc::now());
+        }
+        Ok(proposal)
+    }
+
+    async fn list(&self) -> Result<Vec<Proposal>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM proposals")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let now = Utc::now();
+        rows.into_iter()
+            .map(|(data,)| {
+                let mut proposal: Proposal = serde_json::from_str(&data)?;
+                proposal.expire(now);
+                Ok(proposal)
+            })
+            .collect()
+    }
+
+    /// Applies `change` to proposal `id` and stores the result. The update
+    /// only lands if the stored proposal is unchanged since it was read, and
+    /// is retried on the fresh copy otherwise, so concurrent approvals and
+    /// executions can't overwrite each other.
+    async fn update(&self, id: &str, change: impl Fn(&mut Proposal) -> Result<()>) -> Result<Proposal> {
+        loop {
+            let row: Option<(String,)> = sqlx::query_as("SELECT data FROM proposals WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+            let (data,) = row.ok_or_else(|| anyhow!("No proposal {}", id))?;
+
+            let mut proposal: Proposal = serde_json::from_str(&data)?;
+            proposal.expire(Utc::now());
+            change(&mut proposal)?;
+
+            let updated = sqlx::query("UPDATE proposals SET data = $1 WHERE id = $2 AND data = $3")
+                .bind(serde_json::to_string(&proposal)?)
+                .bind(id)
+                .bind(&data)
+                .execute(&self.pool)
+                .await?;
+            if updated.rows_affected() > 0 {
+                return Ok(proposal);
+            }
+        }
+    }
+}
+
+impl BlockchainService {
+    /// Queues `request` for approval under the sender's `ApprovalRule`. An
+    /// ENS recipient is resolved now, so approvers sign off on an address.
+    pub async fn propose_transaction(&self, request: TransactionRequest) -> Result<Proposal> {
+        let request = self.resolve_request(&request).await?;
+        let rule = self
+            .app
+            .policy_engine()
+            .get_policy(&request.from, request.chain_type)
+            .await
+            .and_then(|policy| policy.approval)
+            .ok_or_else(|| anyhow!("Wallet {} has no approval rule", request.from))?;
+        if rule.quorum == 0 || rule.quorum > rule.approvers.len() {
+            bail!("Approval quorum of {} is unreachable with {} approvers", rule.quorum, rule.approvers.len());
+        }
+
+        let created_at = Utc::now();
+        let proposal = Proposal {
+            id: uuid::Uuid::new_v4().to_string(),
+            request,
+            approvers: rule.approvers,
+            quorum: rule.quorum,
+            approvals: Vec::new(),
+            created_at,
+            expires_at: created_at + Duration::seconds(rule.expiry_secs as i64),
+            status: ProposalStatus::Pending,
+        };
+
+        self.proposals.insert(&proposal).await?;
+        Ok(proposal)
+    }
+
+    /// Records `approver`'s signature over `Proposal::approval_message`. The
+    /// approval that reaches the quorum moves the proposal to `Approved`, for
+    /// the sender to send with `execute_proposal`.
+    pub async fn approve_proposal(&self, id: &str, approver: &str, signature: &Signature) -> Result<Proposal> {
+        self.proposals
+            .update(id, |proposal| {
+                check_pending(proposal)?;
+                let approver = proposal.approver(approver)?;
+                if !verify_signature(&approver, proposal.approval_message().as_bytes(), signature)? {
+                    bail!("Invalid approval signature from {}", approver);
+                }
+                if proposal.approvals.contains(&approver) {
+                    bail!("{} has already approved proposal {}", approver, id);
+                }
+
+                proposal.approvals.push(approver);
+                if proposal.approvals.len() >= proposal.quorum {
+                    proposal.status = ProposalStatus::Approved;
+                }
+                Ok(())
+            })
+            .await
+    }
+
+    /// Rejects the proposal on `approver`'s signature over
+    /// `Proposal::rejection_message`. One rejection is final.
+    pub async fn reject_proposal(
+        &self,
+        id: &str,
+        approver: &str,
+        reason: &str,
+        signature: &Signature,
+    ) -> Result<Proposal> {
+        self.proposals
+            .update(id, |proposal| {
+                check_pending(proposal)?;
+                let approver = proposal.approver(approver)?;
+                if !verify_signature(&approver, proposal.rejection_message(reason).as_bytes(), signature)? {
+                    bail!("Invalid rejection signature from {}", approver);
+                }
+
+                proposal.status = ProposalStatus::Rejected {
+                    by: approver,
+                    reason: reason.to_string(),
+                };
+                Ok(())
+            })
+            .await
+    }
+
+    /// Signs an approved proposal with `sender` and broadcasts it exactly as
+    /// it was approved. A failed send leaves the proposal `Failed`
+    /// rather than `Approved`, so it is never sent, and counted against the
+    /// sender's limits, twice.
+    pub async fn execute_proposal(&self, id: &str, sender: &dyn Signer) -> Result<String> {
+        let proposal = self
+            .proposals
+            .update(id, |proposal| {
+                if proposal.status != ProposalStatus::Approved {
+                    bail!("Proposal {} is {:?}, not approved", id, proposal.status);
+                }
+                let chain_type = &proposal.request.chain_type;
+                if normalize_address(sender.address(), chain_type, self.bitcoin_network)?
+                    != normalize_address(&proposal.request.from, chain_type, self.bitcoin_network)?
+                {
+                    bail!("Signer {} cannot sign for {}", sender.address(), proposal.request.from);
+                }
+                proposal.status = ProposalStatus::Executing;
+                Ok(())
+            })
+            .await?;
+
+        let result = self.send(&proposal.request, sender, true).await;
+
+        let status = match &result {
+            Ok(tx_hash) => ProposalStatus::Executed { tx_hash: tx_hash.clone() },
+            Err(e) => ProposalStatus::Failed { error: e.to_string() },
+        };
+        self.proposals
+            .update(id, |proposal| {
+                proposal.status = status.clone();
+                Ok(())
+            })
+            .await?;
+        result
+    }
+
+    pub async fn get_proposal(&self, id: &str) -> Result<Option<Proposal>> {
+        self.proposals.get(id).await
+    }
+
+    /// Proposals still waiting for approvals, oldest first.
+    pub async fn pending_proposals(&self) -> Result<Vec<Proposal>> {
+        let mut pending: Vec<Proposal> = self
+            .proposals
+            .list()
+            .await?
+            .into_iter()
+            .filter(|proposal| proposal.status == ProposalStatus::Pending)
+            .collect();
+        pending.sort_by_key(|proposal| proposal.created_at);
+        Ok(pending)
+    }
+}
+
+fn check_pending(proposal: &Proposal) -> Result<()> {
+    if proposal.status != ProposalStatus::Pending {
+        bail!("Proposal {} is {:?}, not pending", proposal.id, proposal.status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{App, ChainType};
+    use crate::policy::{ApprovalRule, SpendingPolicy};
+    use crate::wallet::{signing::sign_message, WalletService};
+    use ethers::core::k256::ecdsa::SigningKey;
+    use ethers::utils::public_key_to_address;
+    use std::sync::Arc;
+
+    fn request(from: &str) -> TransactionRequest {
+        TransactionRequest {
+            from: from.to_string(),
+            to: "0x8617e340b3d01fa5f11f306f4090fd50e238070d".to_string(),
+            amount: 25.0,
+            chain_type: ChainType::Ethereum,
+            gas_limit: None,
+            gas_price: None,
+            data: None,
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: None,
+        }
+    }
+
+    struct Approver {
+        key: Vec<u8>,
+        address: String,
+    }
+
+    impl Approver {
+        fn new() -> Self {
+            let key = SigningKey::random(&mut rand::thread_rng());
+            Self {
+                address: format!("{:?}", public_key_to_address(key.verifying_key())),
+                key: key.to_bytes().to_vec(),
+            }
+        }
+
+        fn approve(&self, proposal: &Proposal) -> Signature {
+            sign_message(&self.key, proposal.approval_message().as_bytes()).unwrap()
+        }
+
+        fn reject(&self, proposal: &Proposal, reason: &str) -> Signature {
+            sign_message(&self.key, proposal.rejection_message(reason).as_bytes()).unwrap()
+        }
+    }
+
+    /// A service whose Ethereum node is unreachable, so executed proposals
+    /// fail to send, and a signer for a sending wallet.
+    async fn service() -> (BlockchainService, Arc<dyn Signer>) {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = "sqlite::memory:".to_string();
+        config.wallet.encryption_key_source = "config".to_string();
+        config.blockchain.ethereum_rpc_url = "http://127.0.0.1:1".to_string();
+        let app = Arc::new(App::from_config(config));
+
+        let wallets = WalletService::new(app.clone()).await.unwrap();
+        wallets.unlock_from_config(std::time::Duration::from_secs(60)).await.unwrap();
+        let wallet = wallets.create_wallet(ChainType::Ethereum).await.unwrap();
+        let sender = wallets.signer(&wallet.address).await.unwrap();
+        (BlockchainService::new(app).await.unwrap(), sender)
+    }
+
+    async fn set_rule(
+        service: &BlockchainService,
+        sender: &dyn Signer,
+        approvers: &[&Approver],
+        quorum: usize,
+        expiry_secs: u64,
+    ) {
+        let rule = ApprovalRule {
+            threshold: 1.0,
+            approvers: approvers.iter().map(|approver| approver.address.clone()).collect(),
+            quorum,
+            expiry_secs,
+        };
+        service
+            .app
+            .policy_engine()
+            .set_policy(
+                sender.address(),
+                ChainType::Ethereum,
+                SpendingPolicy { approval: Some(rule), ..Default::default() },
+            )
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_approval_message() {
+        let created_at = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        let mut proposal = Proposal {
+            id: "1b4e28ba-2fa1-11d2-883f-0016d3cca427".to_string(),
+            request: request("0x52908400098527886e0f7030069857d2e4169ee7"),
+            approvers: vec!["0xde709f2102306220921060314715629080e2fb77".to_string()],
+            quorum: 1,
+            approvals: Vec::new(),
+            created_at,
+            expires_at: created_at + Duration::hours(24),
+            status: ProposalStatus::Pending,
+        };
+
+        assert_eq!(
+            proposal.approval_message(),
+            "Approve transaction proposal 1b4e28ba-2fa1-11d2-883f-0016d3cca427\n\
+             Chain: ethereum\n\
+             From: 0x52908400098527886e0f7030069857d2e4169ee7\n\
+             To: 0x8617e340b3d01fa5f11f306f4090fd50e238070d\n\
+             Amount: 25\n\
+             Expires: 2024-05-02T12:00:00Z"
+        );
+        proposal.request.gas_price = Some(30_000_000_000);
+        assert!(proposal.approval_message().contains("\nGas price: 30000000000\n"));
+        assert!(proposal.approver("0xDE709F2102306220921060314715629080E2FB77").is_ok());
+        assert!(proposal.approver("0x8617e340b3d01fa5f11f306f4090fd50e238070d").is_err());
+
+        proposal.expire(created_at + Duration::hours(25));
+        assert_eq!(proposal.status, ProposalStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn test_proposal_quorum() {
+        let (service, sender) = service().await;
+        let sender = sender.as_ref();
+        let (alice, bob, carol, mallory) = (Approver::new(), Approver::new(), Approver::new(), Approver::new());
+        set_rule(&service, sender, &[&alice, &bob, &carol], 2, 3600).await;
+
+        let proposal = service.propose_transaction(request(sender.address())).await.unwrap();
+        let id = &proposal.id;
+        let approved = service
+            .approve_proposal(id, &alice.address, &alice.approve(&proposal))
+            .await
+            .unwrap();
+        assert_eq!(approved.status, ProposalStatus::Pending);
+        assert_eq!(approved.approvals, vec![alice.address.clone()]);
+
+        // Approving twice doesn't count twice, and outsiders can't approve
+        assert!(service
+            .approve_proposal(id, &alice.address, &alice.approve(&proposal))
+            .await
+            .is_err());
+        assert!(service
+            .approve_proposal(id, &mallory.address, &mallory.approve(&proposal))
+            .await
+            .is_err());
+        assert!(service
+            .approve_proposal(id, &bob.address, &mallory.approve(&proposal))
+            .await
+            .is_err());
+        assert_eq!(service.get_proposal(id).await.unwrap().unwrap().approvals.len(), 1);
+
+        // Reaching the quorum only approves; with no node the sender's
+        // execution fails, which is final
+        let approved = service.approve_proposal(id, &bob.address, &bob.approve(&proposal)).await.unwrap();
+        assert_eq!(approved.status, ProposalStatus::Approved);
+        assert!(service.execute_proposal(id, sender).await.is_err());
+        let failed = service.get_proposal(id).await.unwrap().unwrap();
+        assert!(matches!(failed.status, ProposalStatus::Failed { .. }));
+        assert_eq!(failed.approvals.len(), 2);
+        assert!(service.execute_proposal(id, sender).await.is_err());
+        assert!(service
+            .approve_proposal(id, &carol.address, &carol.approve(&proposal))
+            .await
+            .is_err());
+        assert!(service.pending_proposals().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_proposal_rejection_and_expiry() {
+        let (service, sender) = service().await;
+        let sender = sender.as_ref();
+        let (alice, bob) = (Approver::new(), Approver::new());
+        set_rule(&service, sender, &[&alice, &bob], 1, 3600).await;
+
+        let proposal = service.propose_transaction(request(sender.address())).await.unwrap();
+        assert_eq!(service.pending_proposals().await.unwrap().len(), 1);
+        let rejected = service
+            .reject_proposal(&proposal.id, &alice.address, "wrong amount", &alice.reject(&proposal, "wrong amount"))
+            .await
+            .unwrap();
+        assert_eq!(
+            rejected.status,
+            ProposalStatus::Rejected { by: alice.address.clone(), reason: "wrong amount".to_string() }
+        );
+        assert!(service
+            .approve_proposal(&proposal.id, &bob.address, &bob.approve(&proposal))
+            .await
+            .is_err());
+        assert!(service
+            .reject_proposal(&proposal.id, &bob.address, "", &bob.reject(&proposal, ""))
+            .await
+            .is_err());
+
+        set_rule(&service, sender, &[&alice, &bob], 1, 0).await;
+        let proposal = service.propose_transaction(request(sender.address())).await.unwrap();
+        assert_eq!(
+            service.get_proposal(&proposal.id).await.unwrap().unwrap().status,
+            ProposalStatus::Expired
+        );
+        assert!(service
+            .approve_proposal(&proposal.id, &alice.address, &alice.approve(&proposal))
+            .await
+            .is_err());
+        assert!(service.pending_proposals().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,583 @@
+use super::BlockchainService;
+use anyhow::{anyhow, bail, Result};
+use bitcoin::{
+    absolute::LockTime,
+    ecdsa,
+    psbt::Psbt,
+    secp256k1::{Message, Secp256k1, SecretKey},
+    sighash::{EcdsaSighashType, SighashCache},
+    Address, Network, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use bitcoincore_rpc::{
+    json::{ImportDescriptors, Timestamp},
+    Auth, RpcApi,
+};
+use std::collections::HashSet;
+use std::str::FromStr;
+use tokio::sync::{OnceCell, RwLock};
+
+/// Virtual size of the fixed part of a segwit transaction, rounded up.
+const TX_OVERHEAD_VBYTES: u64 = 11;
+/// Virtual size of a signed P2WPKH input.
+const P2WPKH_INPUT_VBYTES: u64 = 68;
+/// Virtual size of a P2WPKH output.
+const P2WPKH_OUTPUT_VBYTES: u64 = 31;
+/// Change below this value is uneconomical to spend and is left to the fee.
+const DUST_LIMIT_SAT: u64 = 294;
+/// Fallback fee rate when the node has no estimate (e.g. on regtest).
+pub const MIN_RELAY_FEE_RATE: u64 = 1;
+/// Confirmation target used for fee rate estimation, in blocks.
+const FEE_ESTIMATE_TARGET_BLOCKS: u16 = 6;
+/// Watch-only wallet on the node that tracks the addresses we spend from.
+const WATCH_WALLET: &str = "defi-wallet-watch";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub value: u64,
+    pub script_pubkey: ScriptBuf,
+    /// Block height of the output; 0 while it is unconfirmed.
+    pub height: u64,
+}
+
+/// Tracks our addresses in a watch-only descriptor wallet on the node. The
+/// node then follows their outputs through the mempool, so outputs spent by
+/// an unconfirmed send are not offered again and its change can be spent at
+/// once, and balances need no scan of the UTXO set.
+pub(crate) struct BitcoinWatcher {
+    client: bitcoincore_rpc::Client,
+    loaded: OnceCell<()>,
+    tracked: RwLock<HashSet<String>>,
+}
+
+impl BitcoinWatcher {
+    pub(crate) fn new(rpc_url: &str, auth: Auth) -> Result<Self> {
+        let url = format!("{}/wallet/{}", rpc_url.trim_end_matches('/'), WATCH_WALLET);
+        Ok(Self {
+            client: bitcoincore_rpc::Client::new(&url, auth)?,
+            loaded: OnceCell::new(),
+            tracked: RwLock::new(HashSet::new()),
+        })
+    }
+
+    /// Creates the watch wallet, or loads it if the node already has it.
+    async fn load(&self) -> Result<()> {
+        self.loaded
+            .get_or_try_init(|| async {
+                if self.client.get_wallet_info().is_ok() {
+                    return Ok(());
+                }
+                if self.client.load_wallet(WATCH_WALLET).is_err() {
+                    self.client.create_wallet(WATCH_WALLET, Some(true), Some(true), None, None)?;
+                }
+                Ok::<_, anyhow::Error>(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Adds `address` to the watch wallet. The first time, the node rescans
+    /// the chain for the address's history, which can take a while.
+    async fn track(&self, address: &Address) -> Result<()> {
+        if self.tracked.read().await.contains(&address.to_string()) {
+            return Ok(());
+        }
+        self.load().await?;
+
+        if !self.client.get_address_info(address)?.is_mine.unwrap_or(false) {
+            let descriptor = self.client.get_descriptor_info(&format!("addr({})", address))?.descriptor;
+            let results = self.client.import_descriptors(ImportDescriptors {
+                descriptor,
+                timestamp: Timestamp::Time(0),
+                active: None,
+                range: None,
+                next_index: None,
+                internal: None,
+                label: None,
+            })?;
+            if let Some(result) = results.iter().find(|result| !result.success) {
+                bail!("Node could not watch {}: {:?}", address, result.error);
+            }
+        }
+
+        self.tracked.write().await.insert(address.to_string());
+        Ok(())
+    }
+
+    /// Outputs paying `address` that no transaction, confirmed or in the
+    /// mempool, spends. Unconfirmed outputs are included when the node
+    /// trusts them, as with change from our own sends.
+    async fn list_unspent(&self, address: &Address) -> Result<Vec<Utxo>> {
+        self.track(address).await?;
+        let tip = self.client.get_block_count()?;
+
+        Ok(self
+            .client
+            .list_unspent(Some(0), None, Some(&[address]), None, None)?
+            .into_iter()
+            .map(|utxo| Utxo {
+                outpoint: OutPoint::new(utxo.txid, utxo.vout),
+                value: utxo.amount.to_sat(),
+                script_pubkey: utxo.script_pub_key,
+                height: match utxo.confirmations {
+                    0 => 0,
+                    confirmations => tip + 1 - u64::from(confirmations),
+                },
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoinSelection {
+    pub inputs: Vec<Utxo>,
+    pub fee: u64,
+    /// Change returned to the sender; zero when it would have been dust.
+    pub change: u64,
+}
+
+impl BlockchainService {
+    pub(crate) fn bitcoin_network(&self) -> Result<Network> {
+        self.bitcoin_network.to_bitcoin()
+    }
+
+    pub(crate) fn parse_bitcoin_address(&self, address: &str) -> Result<Address> {
+        Ok(Address::from_str(address)?.require_network(self.bitcoin_network()?)?)
+    }
+
+    /// Returns the outputs paying to `address` that are not yet spent,
+    /// counting transactions in the mempool: outputs an unconfirmed send
+    /// spends are left out and its change is included.
+    pub async fn list_utxos(&self, address: &str) -> Result<Vec<Utxo>> {
+        let address = self.parse_bitcoin_address(address)?;
+        self.bitcoin_watcher.list_unspent(&address).await
+    }
+
+    /// Estimates a fee rate in sat/vB, falling back to the minimum relay fee.
+    pub async fn estimate_bitcoin_fee_rate(&self) -> Result<u64> {
+        let provider = self.bitcoin_provider.read().await;
+        let estimate = provider.estimate_smart_fee(FEE_ESTIMATE_TARGET_BLOCKS, None)?;
+
+        Ok(estimate
+            .fee_rate
+            .map(|per_kvb| (per_kvb.to_sat() + 999) / 1000)
+            .unwrap_or(MIN_RELAY_FEE_RATE)
+            .max(MIN_RELAY_FEE_RATE))
+    }
+
+    /// Builds an unsigned, replaceable PSBT paying `amount_sat` from `from` to
+    /// `to`, with change returned to `from`. Inputs carry their witness UTXO
+    /// so the PSBT can be signed without access to the chain.
+    pub async fn create_psbt(
+        &self,
+        from: &str,
+        to: &str,
+        amount_sat: u64,
+        fee_rate: Option<u64>,
+    ) -> Result<Psbt> {
+        let from = self.parse_bitcoin_address(from)?;
+        let to = self.parse_bitcoin_address(to)?;
+        let fee_rate = match fee_rate {
+            Some(fee_rate) => fee_rate,
+            None => self.estimate_bitcoin_fee_rate().await?,
+        };
+
+        let utxos = self.list_utxos(&from.to_string()).await?;
+        let selection = select_coins(utxos, amount_sat, fee_rate)?;
+
+        let mut output = vec![TxOut {
+            value: amount_sat,
+            script_pubkey: to.script_pubkey(),
+        }];
+        if selection.change > 0 {
+            output.push(TxOut {
+                value: selection.change,
+                script_pubkey: from.script_pubkey(),
+            });
+        }
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: selection
+                .inputs
+                .iter()
+                .map(|utxo| TxIn {
+                    previous_output: utxo.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::default(),
+                })
+                .collect(),
+            output,
+        };
+
+        let mut psbt = Psbt::from_unsigned_tx(tx)?;
+        for (input, utxo) in psbt.inputs.iter_mut().zip(&selection.inputs) {
+            input.witness_utxo = Some(TxOut {
+                value: utxo.value,
+                script_pubkey: utxo.script_pubkey.clone(),
+            });
+        }
+
+        Ok(psbt)
+    }
+
+    /// Builds a replacement (BIP-125) for the unconfirmed transaction `txid`
+    /// paying `fee_rate`, taking the extra fee out of the change output that
+    /// pays back to `change_address`.
+    pub async fn bump_fee_psbt(&self, txid: &str, change_address: &str, fee_rate: u64) -> Result<Psbt> {
+        let change_script = self.parse_bitcoin_address(change_address)?.script_pubkey();
+        let provider = self.bitcoin_provider.read().await;
+        let original = provider.get_raw_transaction(&Txid::from_str(txid)?, None)?;
+
+        if !original.input.iter().any(|input| input.sequence.is_rbf()) {
+            bail!("Transaction {} does not signal replaceability", txid);
+        }
+
+        let mut prevouts = Vec::with_capacity(original.input.len());
+        for input in &original.input {
+            let previous = provider.get_raw_transaction(&input.previous_output.txid, None)?;
+            let prevout = previous
+                .output
+                .get(input.previous_output.vout as usize)
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing previous output {}", input.previous_output))?;
+            prevouts.push(prevout);
+        }
+
+        let input_value: u64 = prevouts.iter().map(|prevout| prevout.value).sum();
+        let output_value: u64 = original.output.iter().map(|output| output.value).sum();
+        let original_fee = input_value - output_value;
+
+        let vsize = estimate_vsize(original.input.len(), original.output.len());
+        // BIP-125 requires paying at least the incremental relay fee on top.
+        let new_fee = (fee_rate * vsize).max(original_fee + MIN_RELAY_FEE_RATE * vsize);
+        let extra_fee = new_fee - original_fee;
+
+        let mut tx = Transaction {
+            version: original.version,
+            lock_time: original.lock_time,
+            input: original
+                .input
+                .iter()
+                .map(|input| TxIn {
+                    previous_output: input.previous_output,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::default(),
+                })
+                .collect(),
+            output: original.output.clone(),
+        };
+
+        let change = tx
+            .output
+            .iter_mut()
+            .find(|output| output.script_pubkey == change_script)
+            .ok_or_else(|| anyhow!("Transaction {} has no change output to bump from", txid))?;
+        if change.value < extra_fee + DUST_LIMIT_SAT {
+            bail!("Change output too small to pay {} sat of extra fee", extra_fee);
+        }
+        change.value -= extra_fee;
+
+        let mut psbt = Psbt::from_unsigned_tx(tx)?;
+        for (input, prevout) in psbt.inputs.iter_mut().zip(prevouts) {
+            input.witness_utxo = Some(prevout);
+        }
+
+        Ok(psbt)
+    }
+
+    /// Extracts the transaction from a finalized PSBT and broadcasts it.
+    pub async fn broadcast_psbt(&self, psbt: Psbt) -> Result<String> {
+        let tx = psbt.extract_tx();
+        let provider = self.bitcoin_provider.read().await;
+        Ok(provider.send_raw_transaction(&tx)?.to_string())
+    }
+}
+
+/// The fee `psbt` pays: the value of its inputs' witness UTXOs minus its outputs.
+pub fn psbt_fee(psbt: &Psbt) -> Result<u64> {
+    let input_value = psbt
+        .inputs
+        .iter()
+        .map(|input| {
+            input
+                .witness_utxo
+                .as_ref()
+                .map(|prevout| prevout.value)
+                .ok_or_else(|| anyhow!("PSBT input is missing its witness UTXO"))
+        })
+        .sum::<Result<u64>>()?;
+    let output_value: u64 = psbt.unsigned_tx.output.iter().map(|output| output.value).sum();
+
+    input_value
+        .checked_sub(output_value)
+        .ok_or_else(|| anyhow!("PSBT outputs exceed its inputs"))
+}
+
+/// Picks inputs largest-first until they cover `amount` plus the fee for a
+/// P2WPKH transaction at `fee_rate` sat/vB.
+pub fn select_coins(mut utxos: Vec<Utxo>, amount: u64, fee_rate: u64) -> Result<CoinSelection> {
+    utxos.sort_by(|a, b| b.value.cmp(&a.value));
+
+    let mut inputs = Vec::new();
+    let mut total = 0;
+    for utxo in utxos {
+        total += utxo.value;
+        inputs.push(utxo);
+
+        let fee_with_change = fee_rate * estimate_vsize(inputs.len(), 2);
+        if total >= amount + fee_with_change + DUST_LIMIT_SAT {
+            return Ok(CoinSelection {
+                inputs,
+                fee: fee_with_change,
+                change: total - amount - fee_with_change,
+            });
+        }
+
+        let fee_without_change = fee_rate * estimate_vsize(inputs.len(), 1);
+        if total >= amount + fee_without_change {
+            return Ok(CoinSelection {
+                inputs,
+                fee: total - amount,
+                change: 0,
+            });
+        }
+    }
+
+    bail!("Insufficient funds: {} sat available, {} sat requested", total, amount)
+}
+
+pub fn estimate_vsize(inputs: usize, outputs: usize) -> u64 {
+    TX_OVERHEAD_VBYTES + inputs as u64 * P2WPKH_INPUT_VBYTES + outputs as u64 * P2WPKH_OUTPUT_VBYTES
+}
+
+/// Signs every P2WPKH input of `psbt` spendable by `private_key`, adding a
+/// partial signature per BIP-174. Returns the number of inputs signed.
+pub fn sign_psbt(psbt: &mut Psbt, private_key: &[u8]) -> Result<usize> {
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(private_key)?;
+    let public_key = PublicKey::new(secret_key.public_key(&secp));
+    let wpubkey_hash = public_key
+        .wpubkey_hash()
+        .ok_or_else(|| anyhow!("Bitcoin keys must be compressed"))?;
+    let own_script = ScriptBuf::new_v0_p2wpkh(&wpubkey_hash);
+
+    let mut cache = SighashCache::new(&psbt.unsigned_tx);
+    let mut signed = 0;
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        let utxo = match &input.witness_utxo {
+            Some(utxo) if utxo.script_pubkey == own_script => utxo,
+            _ => continue,
+        };
+
+        let script_code = own_script
+            .p2wpkh_script_code()
+            .ok_or_else(|| anyhow!("Invalid P2WPKH script"))?;
+        let sighash = cache.segwit_signature_hash(index, &script_code, utxo.value, EcdsaSighashType::All)?;
+        let sig = secp.sign_ecdsa(&Message::from_slice(sighash.as_ref())?, &secret_key);
+
+        input.partial_sigs.insert(
+            public_key,
+            ecdsa::Signature {
+                sig,
+                hash_ty: EcdsaSighashType::All,
+            },
+        );
+        signed += 1;
+    }
+
+    Ok(signed)
+}
+
+/// Finalizes every P2WPKH input that carries a partial signature, moving it
+/// into the final witness as BIP-174's input finalizer role requires.
+pub fn finalize_psbt(psbt: &mut Psbt) -> Result<()> {
+    for (index, input) in psbt.inputs.iter_mut().enumerate() {
+        if input.final_script_witness.is_some() {
+            continue;
+        }
+
+        let (public_key, signature) = input
+            .partial_sigs
+            .iter()
+            .next()
+            .map(|(public_key, signature)| (*public_key, *signature))
+            .ok_or_else(|| anyhow!("Input {} is not signed", index))?;
+
+        input.final_script_witness = Some(Witness::from_slice(&[signature.to_vec(), public_key.to_bytes()]));
+        input.partial_sigs.clear();
+        input.sighash_type = None;
+        input.bip32_derivation.clear();
+    }
+
+    Ok(())
+}
+
+/// Returns the P2WPKH address of a compressed secp256k1 public key.
+pub fn p2wpkh_address(public_key: &[u8], network: Network) -> Result<Address> {
+    Ok(Address::p2wpkh(&PublicKey::from_slice(public_key)?, network)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::offline::{UnsignedTransaction, OFFLINE_FORMAT_VERSION};
+    use crate::blockchain::{ChainType, TransactionRequest, TransactionStatus};
+    use crate::core::{App, Network as ConfiguredNetwork};
+    use crate::wallet::WalletService;
+    use bitcoin::hashes::Hash;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn utxo(value: u64, script_pubkey: ScriptBuf) -> Utxo {
+        Utxo {
+            outpoint: OutPoint::new(Txid::all_zeros(), value as u32),
+            value,
+            script_pubkey,
+            height: 1,
+        }
+    }
+
+    #[test]
+    fn test_coin_selection() {
+        let script = ScriptBuf::new();
+        let utxos = vec![utxo(10_000, script.clone()), utxo(50_000, script.clone()), utxo(20_000, script)];
+
+        let selection = select_coins(utxos.clone(), 55_000, 2).unwrap();
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.fee, 2 * estimate_vsize(2, 2));
+        assert_eq!(selection.change, 70_000 - 55_000 - selection.fee);
+
+        assert!(select_coins(utxos, 80_000, 2).is_err());
+    }
+
+    #[test]
+    fn test_sign_and_finalize_psbt() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7u8; 32]).unwrap();
+        let public_key = PublicKey::new(secret_key.public_key(&secp));
+        let address = p2wpkh_address(&public_key.to_bytes(), Network::Regtest).unwrap();
+
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::default(),
+            }],
+            output: vec![TxOut {
+                value: 9_000,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 10_000,
+            script_pubkey: address.script_pubkey(),
+        });
+
+        assert_eq!(sign_psbt(&mut psbt, &secret_key.secret_bytes()).unwrap(), 1);
+        finalize_psbt(&mut psbt).unwrap();
+
+        let tx = psbt.extract_tx();
+        assert_eq!(tx.input[0].witness.len(), 2);
+        assert!(tx.input[0].sequence.is_rbf());
+    }
+
+    /// Mines `blocks` regtest blocks paying their reward to `address`.
+    async fn mine(service: &BlockchainService, blocks: u64, address: &str) {
+        let address = service.parse_bitcoin_address(address).unwrap();
+        service.bitcoin_provider.read().await.generate_to_address(blocks, &address).unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs bitcoind -regtest -txindex with wallet support on localhost:18443, rpcuser=user, rpcpassword=pass"]
+    async fn test_send_and_bump_fee_on_regtest() {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = "sqlite::memory:".to_string();
+        config.wallet.encryption_key_source = "config".to_string();
+        config.blockchain.bitcoin_rpc_url = "http://127.0.0.1:18443".to_string();
+        config.blockchain.bitcoin_rpc_user = "user".to_string();
+        config.blockchain.bitcoin_rpc_password = "pass".to_string();
+        config.blockchain.bitcoin_network = ConfiguredNetwork::Regtest;
+        let app = Arc::new(App::from_config(config));
+        let wallets = WalletService::new(app.clone()).await.unwrap();
+        wallets.unlock_from_config(Duration::from_secs(60)).await.unwrap();
+        let service = BlockchainService::new(app).await.unwrap();
+
+        let sender = wallets.create_wallet(ChainType::Bitcoin).await.unwrap().address;
+        let recipient = wallets.create_wallet(ChainType::Bitcoin).await.unwrap().address;
+        let signer = wallets.signer(&sender).await.unwrap();
+
+        // One block reward for the sender, matured by 100 more blocks
+        mine(&service, 1, &sender).await;
+        mine(&service, 100, &recipient).await;
+        let utxos = service.list_utxos(&sender).await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        let funds = utxos[0].value;
+
+        let psbt = service.create_psbt(&sender, &recipient, 100_000, Some(2)).await.unwrap();
+        assert_eq!(psbt_fee(&psbt).unwrap(), 2 * estimate_vsize(1, 2));
+        assert!(psbt.unsigned_tx.input.iter().all(|input| input.sequence.is_rbf()));
+
+        // Sent through the signer, which signs and finalizes the PSBT
+        let request = TransactionRequest {
+            from: sender.clone(),
+            to: recipient.clone(),
+            amount: 0.001,
+            chain_type: ChainType::Bitcoin,
+            gas_limit: None,
+            gas_price: None,
+            data: None,
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: Some(2),
+        };
+        let txid = service.send_transaction(&request, signer.as_ref()).await.unwrap();
+
+        // The spent output is gone at once and the unconfirmed change is offered
+        let utxos = service.list_utxos(&sender).await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint.txid.to_string(), txid);
+        assert_eq!(utxos[0].height, 0);
+
+        let bumped = service.bump_fee_psbt(&txid, &sender, 10).await.unwrap();
+        assert_eq!(psbt_fee(&bumped).unwrap(), 10 * estimate_vsize(1, 2));
+        let unsigned = UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: ChainType::Bitcoin,
+            from: sender.clone(),
+            payload: bumped.serialize(),
+        };
+        let signed = signer.sign_transaction(&unsigned).await.unwrap();
+        let replacement = service.broadcast_signed_transaction(&signed).await.unwrap();
+        assert_ne!(replacement, txid);
+
+        // A second send before confirmation spends the replacement's change
+        // instead of conflicting with it
+        let second = service.send_transaction(&request, signer.as_ref()).await.unwrap();
+        let provider = service.bitcoin_provider.read().await;
+        let second_tx = provider.get_raw_transaction(&Txid::from_str(&second).unwrap(), None).unwrap();
+        drop(provider);
+        assert_eq!(second_tx.input.len(), 1);
+        assert_eq!(second_tx.input[0].previous_output.txid.to_string(), replacement);
+
+        mine(&service, 1, &recipient).await;
+        for txid in [&replacement, &second] {
+            assert!(matches!(
+                service.get_transaction_status(txid, ChainType::Bitcoin).await.unwrap(),
+                TransactionStatus::Confirmed
+            ));
+        }
+        let change: u64 = service.list_utxos(&sender).await.unwrap().iter().map(|utxo| utxo.value).sum();
+        assert_eq!(change, funds - 2 * 100_000 - 10 * estimate_vsize(1, 2) - 2 * estimate_vsize(1, 2));
+        assert_eq!(service.get_balance(&sender, ChainType::Bitcoin).await.unwrap(), change as f64 / 1e8);
+    }
+}
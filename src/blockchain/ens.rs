@@ -0,0 +1,553 @@
+use crate::core::BlockchainConfig;
+use anyhow::{anyhow, bail, Result};
+use ethers::{
+    abi::{decode, encode, AbiDecode, AbiEncode, ParamType, Token},
+    contract::abigen,
+    providers::{Http, Middleware, Provider, RpcError},
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, H256},
+    utils::keccak256,
+};
+use ens_normalize_rs::EnsNameNormalizer;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+abigen!(
+    EnsRegistry,
+    r#"[
+        function resolver(bytes32 node) external view returns (address)
+    ]"#
+);
+
+abigen!(
+    PublicResolver,
+    r#"[
+        function addr(bytes32 node) external view returns (address)
+        function name(bytes32 node) external view returns (string)
+        function supportsInterface(bytes4 interfaceId) external view returns (bool)
+        function resolve(bytes name, bytes data) external view returns (bytes)
+    ]"#
+);
+
+/// ENSIP-10 `IExtendedResolver` interface ID.
+const EXTENDED_RESOLVER_INTERFACE: [u8; 4] = [0x90, 0x61, 0xb9, 0x23];
+
+/// Selector of EIP-3668's
+/// `OffchainLookup(address,string[],bytes,bytes4,bytes)` revert.
+const OFFCHAIN_LOOKUP_SELECTOR: [u8; 4] = [0x55, 0x6f, 0x18, 0x30];
+
+/// Offchain lookups followed per call before giving up, as EIP-3668
+/// recommends.
+const MAX_CCIP_REDIRECTS: usize = 4;
+
+/// How long a CCIP-read gateway gets to answer before the next one is tried.
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves ENS names through the registry, including wildcard (ENSIP-10)
+/// and offchain (EIP-3668 CCIP-read) resolvers.
+#[derive(Clone)]
+pub struct EnsResolver {
+    provider: Arc<Provider<Http>>,
+    registry: Address,
+    gateway: reqwest::Client,
+}
+
+/// The revert an offchain resolver uses to send us to its gateway.
+#[derive(Debug, Clone, PartialEq)]
+struct OffchainLookup {
+    sender: Address,
+    urls: Vec<String>,
+    call_data: Bytes,
+    callback_function: [u8; 4],
+    extra_data: Bytes,
+}
+
+impl EnsResolver {
+    pub fn new(provider: Provider<Http>, registry: &str) -> Result<Self> {
+        Ok(Self {
+            provider: Arc::new(provider),
+            registry: registry.parse()?,
+            gateway: reqwest::Client::builder().timeout(GATEWAY_TIMEOUT).build()?,
+        })
+    }
+
+    pub fn from_config(config: &BlockchainConfig) -> Result<Self> {
+        Self::new(
+            Provider::<Http>::try_from(&config.ethereum_rpc_url)?,
+            &config.ens_registry_address,
+        )
+    }
+
+    /// The address `name` resolves to.
+    pub async fn resolve_name(&self, name: &str) -> Result<Address> {
+        let name = normalize(name)?;
+        match self.addr(&name).await? {
+            Some(address) => Ok(address),
+            None => bail!("{} has no address record", name),
+        }
+    }
+
+    /// The primary name of `address`, if it has one that resolves back to it.
+    /// Node and gateway failures are errors rather than `None`.
+    pub async fn lookup_address(&self, address: Address) -> Result<Option<String>> {
+        let reverse_name = format!("{:x}.addr.reverse", address);
+        let call = NameCall { node: namehash(&reverse_name).0 }.encode();
+        let name = match self.call_resolver(&reverse_name, call.into()).await? {
+            Some(result) => NameReturn::decode(result)?.0,
+            None => return Ok(None),
+        };
+        // A reverse record is just a string its owner set; it need not be a
+        // valid name
+        let Ok(name) = normalize(&name) else {
+            return Ok(None);
+        };
+
+        // Anyone can claim any name in reverse records; only trust it if
+        // the name's owner agrees.
+        Ok((self.addr(&name).await? == Some(address)).then_some(name))
+    }
+
+    /// Resolves `to` if it is an ENS name; anything else is returned as is.
+    pub async fn resolve_recipient(&self, to: &str) -> Result<String> {
+        if !is_ens_name(to) {
+            return Ok(to.to_string());
+        }
+        Ok(format!("{:?}", self.resolve_name(to).await?))
+    }
+
+    /// The address record of normalized `name`, `None` if it has no resolver
+    /// or no address.
+    async fn addr(&self, name: &str) -> Result<Option<Address>> {
+        let call = AddrCall { node: namehash(name).0 }.encode();
+        let Some(result) = self.call_resolver(name, call.into()).await? else {
+            return Ok(None);
+        };
+        let address = AddrReturn::decode(result)?.0;
+        Ok((!address.is_zero()).then_some(address))
+    }
+
+    /// Sends `data` to the resolver of `name`, through `resolve` when it is
+    /// an extended resolver. A resolver found on a parent name must be one;
+    /// `None` when `name` has no usable resolver.
+    async fn call_resolver(&self, name: &str, data: Bytes) -> Result<Option<Bytes>> {
+        let Some((resolver, exact)) = self.find_resolver(name).await? else {
+            return Ok(None);
+        };
+        let contract = PublicResolver::new(resolver, self.provider.clone());
+        let extended = contract
+            .supports_interface(EXTENDED_RESOLVER_INTERFACE)
+            .call()
+            .await
+            .unwrap_or(false);
+
+        if extended {
+            let call = ResolveCall {
+                name: dns_encode(name)?.into(),
+                data,
+            }
+            .encode();
+            let result = self.ccip_call(resolver, call.into()).await?;
+            Ok(Some(ResolveReturn::decode(result)?.0))
+        } else if exact {
+            Ok(Some(self.ccip_call(resolver, data).await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The resolver of `name` or, failing that, of its closest parent, and
+    /// whether it was set on `name` itself.
+    async fn find_resolver(&self, name: &str) -> Result<Option<(Address, bool)>> {
+        let registry = EnsRegistry::new(self.registry, self.provider.clone());
+        let mut current = name;
+        loop {
+            let resolver = registry.resolver(namehash(current).0).call().await?;
+            if !resolver.is_zero() {
+                return Ok(Some((resolver, current == name)));
+            }
+            match current.split_once('.') {
+                Some((_, parent)) => current = parent,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// `eth_call` that follows `OffchainLookup` reverts from `to` through its
+    /// gateways (EIP-3668).
+    async fn ccip_call(&self, to: Address, mut data: Bytes) -> Result<Bytes> {
+        for _ in 0..=MAX_CCIP_REDIRECTS {
+            let tx: TypedTransaction = ethers::types::TransactionRequest::new().to(to).data(data.clone()).into();
+            let error = match self.provider.call(&tx, None).await {
+                Ok(result) => return Ok(result),
+                Err(error) => error,
+            };
+
+            let lookup = match error
+                .as_error_response()
+                .and_then(|response| response.as_revert_data())
+                .and_then(|revert| OffchainLookup::decode(&revert))
+            {
+                Some(lookup) => lookup,
+                None => return Err(error.into()),
+            };
+            if lookup.sender != to {
+                bail!("Offchain lookup sender {:?} is not the resolver {:?}", lookup.sender, to);
+            }
+
+            let response = self.fetch_gateway(&lookup).await?;
+            data = [
+                lookup.callback_function.as_slice(),
+                &encode(&[Token::Bytes(response.to_vec()), Token::Bytes(lookup.extra_data.to_vec())]),
+            ]
+            .concat()
+            .into();
+        }
+        bail!("Too many offchain lookups from {:?}", to)
+    }
+
+    /// Asks the lookup's gateways in turn for a response. A gateway that
+    /// answers with a 4xx status ends the lookup; other failures move on to
+    /// the next one.
+    async fn fetch_gateway(&self, lookup: &OffchainLookup) -> Result<Bytes> {
+        let sender = format!("{:?}", lookup.sender);
+        let data = format!("0x{}", hex::encode(&lookup.call_data));
+        let mut last_error = anyhow!("Offchain lookup has no gateway URLs");
+
+        for template in &lookup.urls {
+            // URLs with a {data} placeholder are fetched with GET, others
+            // get the request as a JSON POST body
+            let url = template.replace("{sender}", &sender).replace("{data}", &data);
+            let request = if template.contains("{data}") {
+                self.gateway.get(&url)
+            } else {
+                self.gateway.post(&url).json(&json!({ "sender": sender, "data": data }))
+            };
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(error) => {
+                    last_error = error.into();
+                    continue;
+                }
+            };
+            let status = response.status();
+            if status.is_client_error() {
+                bail!("Gateway {} rejected the offchain lookup: {}", url, status);
+            }
+            if !status.is_success() {
+                last_error = anyhow!("Gateway {} failed: {}", url, status);
+                continue;
+            }
+
+            let body: Value = response.json().await?;
+            let result = body
+                .get("data")
+                .ok_or_else(|| anyhow!("Gateway {} returned no data", url))?;
+            return Ok(serde_json::from_value(result.clone())?);
+        }
+        Err(last_error)
+    }
+}
+
+impl OffchainLookup {
+    fn decode(revert: &[u8]) -> Option<Self> {
+        let args = revert.strip_prefix(OFFCHAIN_LOOKUP_SELECTOR.as_slice())?;
+        let mut tokens = decode(
+            &[
+                ParamType::Address,
+                ParamType::Array(Box::new(ParamType::String)),
+                ParamType::Bytes,
+                ParamType::FixedBytes(4),
+                ParamType::Bytes,
+            ],
+            args,
+        )
+        .ok()?
+        .into_iter();
+
+        Some(Self {
+            sender: tokens.next()?.into_address()?,
+            urls: tokens
+                .next()?
+                .into_array()?
+                .into_iter()
+                .map(|url| url.into_string())
+                .collect::<Option<_>>()?,
+            call_data: tokens.next()?.into_bytes()?.into(),
+            callback_function: tokens.next()?.into_fixed_bytes()?.try_into().ok()?,
+            extra_data: tokens.next()?.into_bytes()?.into(),
+        })
+    }
+}
+
+/// Whether `s` should be resolved as an ENS name rather than parsed as an
+/// address.
+pub fn is_ens_name(s: &str) -> bool {
+    let s = s.trim();
+    s.contains('.') && s.parse::<Address>().is_err()
+}
+
+/// Normalizes `name` following ENSIP-15, including its confusable and
+/// mixed-script checks.
+pub fn normalize(name: &str) -> Result<String> {
+    EnsNameNormalizer::default()
+        .normalize(name.trim())
+        .map_err(|e| anyhow!("Invalid ENS name {:?}: {}", name, e))
+}
+
+/// EIP-137 namehash of a normalized name.
+pub fn namehash(name: &str) -> H256 {
+    if name.is_empty() {
+        return H256::zero();
+    }
+    H256(name.rsplit('.').fold([0u8; 32], |node, label| {
+        keccak256([node, keccak256(label.as_bytes())].concat())
+    }))
+}
+
+/// DNS wire-format encoding of a name, as ENSIP-10 `resolve` expects.
+pub fn dns_encode(name: &str) -> Result<Vec<u8>> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 255 {
+            bail!("Cannot DNS-encode label {:?}", label);
+        }
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const CALLBACK: [u8; 4] = [0xde, 0xad, 0xbe, 0xef];
+    const GATEWAY_RESPONSE: [u8; 2] = [0x12, 0x34];
+
+    #[test]
+    fn test_name_normalization_and_hashing() {
+        assert_eq!(normalize("Alice.ETH").unwrap(), "alice.eth");
+        assert_eq!(normalize("_dmarc.alice.eth").unwrap(), "_dmarc.alice.eth");
+        assert_eq!(normalize("caf\u{65}\u{301}.eth").unwrap(), "caf\u{e9}.eth");
+        assert!(normalize("alice..eth").is_err());
+        assert!(normalize("al_ice.eth").is_err());
+        assert!(normalize("ab--cd.eth").is_err());
+        assert!(normalize("al ice.eth").is_err());
+        assert!(normalize("alice!.eth").is_err());
+        // Whole-script confusables: a Cyrillic "а" among Latin letters
+        assert!(normalize("p\u{430}ypal.eth").is_err());
+
+        assert_eq!(namehash(""), H256::zero());
+        assert_eq!(
+            format!("{:?}", namehash("eth")),
+            "0x93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+        );
+        assert_eq!(
+            format!("{:?}", namehash("foo.eth")),
+            "0xde9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+        );
+        assert_eq!(dns_encode("alice.eth").unwrap(), b"\x05alice\x03eth\x00");
+
+        assert!(is_ens_name("alice.eth"));
+        assert!(!is_ens_name("0x52908400098527886e0f7030069857d2e4169ee7"));
+        assert!(!is_ens_name("alice"));
+    }
+
+    #[test]
+    fn test_offchain_lookup_decoding() {
+        let lookup = OffchainLookup {
+            sender: Address::repeat_byte(0x11),
+            urls: vec!["https://gateway.example.com/{sender}/{data}.json".to_string()],
+            call_data: vec![0x01, 0x02].into(),
+            callback_function: [0xde, 0xad, 0xbe, 0xef],
+            extra_data: vec![0x03].into(),
+        };
+        let revert = [
+            OFFCHAIN_LOOKUP_SELECTOR.as_slice(),
+            &encode(&[
+                Token::Address(lookup.sender),
+                Token::Array(vec![Token::String(lookup.urls[0].clone())]),
+                Token::Bytes(lookup.call_data.to_vec()),
+                Token::FixedBytes(lookup.callback_function.to_vec()),
+                Token::Bytes(lookup.extra_data.to_vec()),
+            ]),
+        ]
+        .concat();
+
+        assert_eq!(OffchainLookup::decode(&revert), Some(lookup));
+        assert_eq!(OffchainLookup::decode(&revert[4..]), None);
+    }
+
+    /// Runs the validation tests published with the ENSIP-15 reference
+    /// implementation (`validate/tests.json` in adraffy/ens-normalize.js).
+    #[test]
+    #[ignore = "needs the ENSIP-15 validation tests at tests/data/ensip15-tests.json"]
+    fn test_ensip15_validation_vectors() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/ensip15-tests.json");
+        let tests: Vec<Value> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+
+        let mut failures = Vec::new();
+        // The first entry describes the Unicode and spec versions
+        for test in tests.iter().filter(|test| test["name"] != "version info") {
+            let name = test["name"].as_str().unwrap();
+            let result = EnsNameNormalizer::default().normalize(name).ok();
+            let expected = match test.get("error") {
+                Some(Value::Bool(true)) => None,
+                _ => Some(test.get("norm").and_then(Value::as_str).unwrap_or(name).to_string()),
+            };
+            if result != expected {
+                failures.push(format!("{:?}: expected {:?}, got {:?}", name, expected, result));
+            }
+        }
+        assert!(failures.is_empty(), "{} failures:\n{}", failures.len(), failures.join("\n"));
+    }
+
+    /// Stands in for both an Ethereum node, whose resolver at any address
+    /// answers with an `OffchainLookup` revert until called back, and the
+    /// gateways it names.
+    async fn serve_ccip(listener: TcpListener) {
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve_ccip_connection(stream, base.clone()));
+        }
+    }
+
+    async fn serve_ccip_connection(stream: TcpStream, base: String) {
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut request_line = String::new();
+            if stream.read_line(&mut request_line).await.unwrap() == 0 {
+                return;
+            }
+            let path = request_line.split_whitespace().nth(1).unwrap().to_string();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+
+            let gateway_data = json!({ "data": format!("0x{}", hex::encode(GATEWAY_RESPONSE)) });
+            let (status, response) = match path.as_str() {
+                "/" => (200, rpc_response(&serde_json::from_slice(&body).unwrap(), &base)),
+                "/unavailable" => (503, json!({})),
+                "/rejected" => (404, json!({})),
+                "/post" => {
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    assert_eq!(request["data"], "0xabcd");
+                    (200, gateway_data)
+                }
+                path => {
+                    assert!(path.starts_with("/get/0x") && path.ends_with("/0xabcd.json"), "{}", path);
+                    (200, gateway_data)
+                }
+            };
+
+            let response = response.to_string();
+            let reply = format!(
+                "HTTP/1.1 {} Stand-in\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            );
+            stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    /// `eth_call` to the stand-in resolver: the callback returns its
+    /// arguments, anything else reverts with a lookup whose first gateway is
+    /// down.
+    fn rpc_response(request: &Value, base: &str) -> Value {
+        assert_eq!(request["method"], "eth_call");
+        let call = &request["params"][0];
+        let data = hex::decode(call["data"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+        if let Some(args) = data.strip_prefix(CALLBACK.as_slice()) {
+            return json!({ "jsonrpc": "2.0", "id": request["id"], "result": format!("0x{}", hex::encode(args)) });
+        }
+
+        let revert = [
+            OFFCHAIN_LOOKUP_SELECTOR.as_slice(),
+            &encode(&[
+                Token::Address(call["to"].as_str().unwrap().parse().unwrap()),
+                Token::Array(vec![
+                    Token::String(format!("{}/unavailable", base)),
+                    Token::String(format!("{}/get/{{sender}}/{{data}}.json", base)),
+                ]),
+                Token::Bytes(data),
+                Token::FixedBytes(CALLBACK.to_vec()),
+                Token::Bytes(vec![0x03]),
+            ]),
+        ]
+        .concat();
+        json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "error": { "code": 3, "message": "execution reverted", "data": format!("0x{}", hex::encode(revert)) },
+        })
+    }
+
+    #[tokio::test]
+    async fn test_ccip_read_through_stand_in_gateway() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_ccip(listener));
+        let provider = Provider::<Http>::try_from(base.as_str()).unwrap();
+        let resolver = EnsResolver::new(provider, &format!("{:?}", Address::zero())).unwrap();
+
+        // The unavailable gateway is skipped and the callback gets the
+        // second gateway's response with the extra data
+        let result = resolver.ccip_call(Address::repeat_byte(0x11), vec![0xab, 0xcd].into()).await.unwrap();
+        assert_eq!(
+            result.to_vec(),
+            encode(&[Token::Bytes(GATEWAY_RESPONSE.to_vec()), Token::Bytes(vec![0x03])])
+        );
+
+        let mut lookup = OffchainLookup {
+            sender: Address::repeat_byte(0x11),
+            urls: vec![format!("{}/post", base)],
+            call_data: vec![0xab, 0xcd].into(),
+            callback_function: CALLBACK,
+            extra_data: Bytes::new(),
+        };
+        assert_eq!(resolver.fetch_gateway(&lookup).await.unwrap().to_vec(), GATEWAY_RESPONSE);
+
+        // A 4xx ends the lookup even with gateways left to try
+        lookup.urls.insert(0, format!("{}/rejected", base));
+        assert!(resolver.fetch_gateway(&lookup).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_reports_node_errors() {
+        let provider = Provider::<Http>::try_from("http://127.0.0.1:1").unwrap();
+        let resolver = EnsResolver::new(provider, "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e").unwrap();
+        assert!(resolver.lookup_address(Address::repeat_byte(0x11)).await.is_err());
+        assert!(resolver.resolve_name("alice.eth").await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil on localhost:8545 forked from mainnet"]
+    async fn test_ens_on_local_fork() {
+        let provider = Provider::<Http>::try_from("http://127.0.0.1:8545").unwrap();
+        let resolver = EnsResolver::new(provider, "0x00000000000C2E074eC69A0dFb2997BA6C7d2e1e").unwrap();
+        let vitalik = "0xd8dA6BF26964aF9D7eEd9e03E53415D37aA96045".parse::<Address>().unwrap();
+
+        assert_eq!(resolver.resolve_name("Vitalik.eth").await.unwrap(), vitalik);
+        assert_eq!(resolver.lookup_address(vitalik).await.unwrap(), Some("vitalik.eth".to_string()));
+        assert_eq!(resolver.lookup_address(Address::repeat_byte(0x11)).await.unwrap(), None);
+        assert!(resolver.resolve_name("this-name-is-not-registered-ens-test.eth").await.is_err());
+    }
+}
@@ -0,0 +1,705 @@
+use super::{BlockchainService, ChainType, TransactionRequest};
+use crate::wallet::signer::Signer;
+use anyhow::{anyhow, bail, Result};
+use ethers::{
+    abi::{encode, AbiDecode, Token},
+    contract::abigen,
+    providers::{Http, JsonRpcClient, Middleware},
+    types::{Address, Bytes, H256, U256},
+    utils::keccak256,
+};
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+
+abigen!(
+    EntryPoint,
+    r#"[
+        function getNonce(address sender, uint192 key) external view returns (uint256)
+    ]"#
+);
+
+abigen!(
+    SimpleAccountFactory,
+    r#"[
+        function createAccount(address owner, uint256 salt) external returns (address)
+        function getAddress(address owner, uint256 salt) external view returns (address)
+    ]"#
+);
+
+abigen!(
+    SimpleAccount,
+    r#"[
+        function execute(address dest, uint256 value, bytes func) external
+        function owner() external view returns (address)
+    ]"#
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPointVersion {
+    V06,
+    V07,
+}
+
+/// An ERC-4337 user operation in unpacked form. It covers both the v0.6
+/// `UserOperation` and the v0.7 `PackedUserOperation`; the version is chosen
+/// when hashing or encoding it for a bundler.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserOperation {
+    pub sender: Address,
+    pub nonce: U256,
+    /// Factory deploying `sender` with its first operation; `None` once the
+    /// account exists.
+    pub factory: Option<Address>,
+    pub factory_data: Bytes,
+    pub call_data: Bytes,
+    pub call_gas_limit: U256,
+    pub verification_gas_limit: U256,
+    pub pre_verification_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub max_priority_fee_per_gas: U256,
+    /// Paymaster sponsoring the operation's gas, if any.
+    pub paymaster: Option<Address>,
+    /// v0.7 only; v0.6 paymasters are covered by `verification_gas_limit`.
+    pub paymaster_verification_gas_limit: U256,
+    /// v0.7 only.
+    pub paymaster_post_op_gas_limit: U256,
+    pub paymaster_data: Bytes,
+    pub signature: Bytes,
+}
+
+impl UserOperation {
+    /// Most the operation can charge for gas, as the EntryPoint prefunds it:
+    /// every gas limit at `max_fee_per_gas`, with v0.6 paymasters allowed
+    /// three times the verification gas for validation and `postOp`.
+    pub fn max_gas_cost(&self, version: EntryPointVersion) -> U256 {
+        let verification_gas = match (version, self.paymaster) {
+            (EntryPointVersion::V06, Some(_)) => self.verification_gas_limit * 3,
+            (EntryPointVersion::V06, None) => self.verification_gas_limit,
+            (EntryPointVersion::V07, _) => {
+                self.verification_gas_limit
+                    + self.paymaster_verification_gas_limit
+                    + self.paymaster_post_op_gas_limit
+            }
+        };
+        (self.call_gas_limit + verification_gas + self.pre_verification_gas) * self.max_fee_per_gas
+    }
+
+    /// `factory || factoryData`, empty for deployed accounts.
+    pub fn init_code(&self) -> Bytes {
+        match self.factory {
+            Some(factory) => [factory.as_bytes(), self.factory_data.as_ref()].concat().into(),
+            None => Bytes::new(),
+        }
+    }
+
+    /// `paymaster || paymasterData` for v0.6, with the two 16-byte paymaster
+    /// gas limits between them for v0.7. Empty without a paymaster.
+    pub fn paymaster_and_data(&self, version: EntryPointVersion) -> Bytes {
+        let Some(paymaster) = self.paymaster else {
+            return Bytes::new();
+        };
+
+        let mut packed = paymaster.as_bytes().to_vec();
+        if version == EntryPointVersion::V07 {
+            packed.extend_from_slice(&u128_bytes(self.paymaster_verification_gas_limit));
+            packed.extend_from_slice(&u128_bytes(self.paymaster_post_op_gas_limit));
+        }
+        packed.extend_from_slice(&self.paymaster_data);
+        packed.into()
+    }
+
+    /// The hash the account owner signs: the packed operation hashed
+    /// together with the EntryPoint address and chain ID.
+    pub fn hash(&self, version: EntryPointVersion, entry_point: Address, chain_id: u64) -> H256 {
+        let hashed = |bytes: &Bytes| Token::FixedBytes(keccak256(bytes).to_vec());
+        let packed = match version {
+            EntryPointVersion::V06 => encode(&[
+                Token::Address(self.sender),
+                Token::Uint(self.nonce),
+                hashed(&self.init_code()),
+                hashed(&self.call_data),
+                Token::Uint(self.call_gas_limit),
+                Token::Uint(self.verification_gas_limit),
+                Token::Uint(self.pre_verification_gas),
+                Token::Uint(self.max_fee_per_gas),
+                Token::Uint(self.max_priority_fee_per_gas),
+                hashed(&self.paymaster_and_data(version)),
+            ]),
+            EntryPointVersion::V07 => encode(&[
+                Token::Address(self.sender),
+                Token::Uint(self.nonce),
+                hashed(&self.init_code()),
+                hashed(&self.call_data),
+                Token::FixedBytes(pack_u128s(self.verification_gas_limit, self.call_gas_limit)),
+                Token::Uint(self.pre_verification_gas),
+                Token::FixedBytes(pack_u128s(self.max_priority_fee_per_gas, self.max_fee_per_gas)),
+                hashed(&self.paymaster_and_data(version)),
+            ]),
+        };
+
+        H256(keccak256(encode(&[
+            Token::FixedBytes(keccak256(packed).to_vec()),
+            Token::Address(entry_point),
+            Token::Uint(chain_id.into()),
+        ])))
+    }
+
+    /// The JSON-RPC form bundlers accept for `version`.
+    pub fn to_rpc(&self, version: EntryPointVersion) -> Value {
+        let mut op = Map::new();
+        op.insert("sender".into(), json!(self.sender));
+        op.insert("nonce".into(), json!(self.nonce));
+        match version {
+            EntryPointVersion::V06 => {
+                op.insert("initCode".into(), json!(self.init_code()));
+            }
+            EntryPointVersion::V07 => {
+                if let Some(factory) = self.factory {
+                    op.insert("factory".into(), json!(factory));
+                    op.insert("factoryData".into(), json!(self.factory_data));
+                }
+            }
+        }
+        op.insert("callData".into(), json!(self.call_data));
+        op.insert("callGasLimit".into(), json!(self.call_gas_limit));
+        op.insert("verificationGasLimit".into(), json!(self.verification_gas_limit));
+        op.insert("preVerificationGas".into(), json!(self.pre_verification_gas));
+        op.insert("maxFeePerGas".into(), json!(self.max_fee_per_gas));
+        op.insert("maxPriorityFeePerGas".into(), json!(self.max_priority_fee_per_gas));
+        match version {
+            EntryPointVersion::V06 => {
+                op.insert("paymasterAndData".into(), json!(self.paymaster_and_data(version)));
+            }
+            EntryPointVersion::V07 => {
+                if let Some(paymaster) = self.paymaster {
+                    op.insert("paymaster".into(), json!(paymaster));
+                    op.insert(
+                        "paymasterVerificationGasLimit".into(),
+                        json!(self.paymaster_verification_gas_limit),
+                    );
+                    op.insert("paymasterPostOpGasLimit".into(), json!(self.paymaster_post_op_gas_limit));
+                    op.insert("paymasterData".into(), json!(self.paymaster_data));
+                }
+            }
+        }
+        op.insert("signature".into(), json!(self.signature));
+        Value::Object(op)
+    }
+
+    /// Applies an ERC-7677 paymaster response: `paymasterAndData` for v0.6,
+    /// separate `paymaster*` fields for v0.7.
+    fn apply_paymaster_response(&mut self, response: &Value) -> Result<()> {
+        if let Some(paymaster_and_data) = response.get("paymasterAndData") {
+            let bytes: Bytes = serde_json::from_value(paymaster_and_data.clone())?;
+            if bytes.len() < 20 {
+                bail!("paymasterAndData is shorter than an address");
+            }
+            self.paymaster = Some(Address::from_slice(&bytes[..20]));
+            self.paymaster_data = bytes[20..].to_vec().into();
+            return Ok(());
+        }
+
+        let paymaster = response
+            .get("paymaster")
+            .ok_or_else(|| anyhow!("Paymaster response has no paymaster"))?;
+        self.paymaster = Some(serde_json::from_value(paymaster.clone())?);
+        self.paymaster_data = match response.get("paymasterData") {
+            Some(data) => serde_json::from_value(data.clone())?,
+            None => Bytes::new(),
+        };
+        if let Some(limit) = response.get("paymasterVerificationGasLimit") {
+            self.paymaster_verification_gas_limit = quantity(limit)?;
+        }
+        if let Some(limit) = response.get("paymasterPostOpGasLimit") {
+            self.paymaster_post_op_gas_limit = quantity(limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gas limits returned by `eth_estimateUserOperationGas`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserOperationGasEstimate {
+    pub pre_verification_gas: U256,
+    pub verification_gas_limit: U256,
+    pub call_gas_limit: U256,
+    /// v0.7 bundlers only, when the operation has a paymaster.
+    pub paymaster_verification_gas_limit: Option<U256>,
+    pub paymaster_post_op_gas_limit: Option<U256>,
+}
+
+impl UserOperationGasEstimate {
+    pub fn apply(&self, op: &mut UserOperation) {
+        op.pre_verification_gas = self.pre_verification_gas;
+        op.verification_gas_limit = self.verification_gas_limit;
+        op.call_gas_limit = self.call_gas_limit;
+        if let Some(limit) = self.paymaster_verification_gas_limit {
+            op.paymaster_verification_gas_limit = limit;
+        }
+        if let Some(limit) = self.paymaster_post_op_gas_limit {
+            op.paymaster_post_op_gas_limit = limit;
+        }
+    }
+}
+
+/// Client for an ERC-4337 bundler's JSON-RPC API. The ERC-7677 paymaster
+/// methods go to the paymaster service, which is often the bundler itself.
+pub struct BundlerClient {
+    client: Http,
+}
+
+impl BundlerClient {
+    pub fn new(url: &str) -> Result<Self> {
+        Ok(Self {
+            client: Http::from_str(url)?,
+        })
+    }
+
+    pub async fn supported_entry_points(&self) -> Result<Vec<Address>> {
+        Ok(self.client.request("eth_supportedEntryPoints", ()).await?)
+    }
+
+    pub async fn estimate_user_operation_gas(
+        &self,
+        op: &UserOperation,
+        version: EntryPointVersion,
+        entry_point: Address,
+    ) -> Result<UserOperationGasEstimate> {
+        let estimate: Value = self
+            .client
+            .request("eth_estimateUserOperationGas", (op.to_rpc(version), entry_point))
+            .await?;
+
+        let field = |name: &str| -> Result<U256> {
+            quantity(estimate.get(name).ok_or_else(|| anyhow!("Gas estimate has no {}", name))?)
+        };
+        let optional = |name: &str| estimate.get(name).map(quantity).transpose();
+        Ok(UserOperationGasEstimate {
+            pre_verification_gas: field("preVerificationGas")?,
+            verification_gas_limit: field("verificationGasLimit")?,
+            call_gas_limit: field("callGasLimit")?,
+            paymaster_verification_gas_limit: optional("paymasterVerificationGasLimit")?,
+            paymaster_post_op_gas_limit: optional("paymasterPostOpGasLimit")?,
+        })
+    }
+
+    /// Submits a signed operation and returns its user operation hash.
+    pub async fn send_user_operation(
+        &self,
+        op: &UserOperation,
+        version: EntryPointVersion,
+        entry_point: Address,
+    ) -> Result<H256> {
+        Ok(self
+            .client
+            .request("eth_sendUserOperation", (op.to_rpc(version), entry_point))
+            .await?)
+    }
+
+    /// The receipt of an included operation, or `None` while it is pending.
+    pub async fn get_user_operation_receipt(&self, hash: H256) -> Result<Option<Value>> {
+        Ok(self.client.request("eth_getUserOperationReceipt", [hash]).await?)
+    }
+
+    /// ERC-7677 `pm_getPaymasterStubData`: placeholder paymaster fields to
+    /// estimate gas with.
+    pub async fn paymaster_stub_data(
+        &self,
+        op: &mut UserOperation,
+        version: EntryPointVersion,
+        entry_point: Address,
+        chain_id: u64,
+    ) -> Result<()> {
+        self.paymaster_request("pm_getPaymasterStubData", op, version, entry_point, chain_id)
+            .await
+    }
+
+    /// ERC-7677 `pm_getPaymasterData`: the final, signed sponsorship for an
+    /// operation whose gas limits are set.
+    pub async fn paymaster_data(
+        &self,
+        op: &mut UserOperation,
+        version: EntryPointVersion,
+        entry_point: Address,
+        chain_id: u64,
+    ) -> Result<()> {
+        self.paymaster_request("pm_getPaymasterData", op, version, entry_point, chain_id)
+            .await
+    }
+
+    async fn paymaster_request(
+        &self,
+        method: &str,
+        op: &mut UserOperation,
+        version: EntryPointVersion,
+        entry_point: Address,
+        chain_id: u64,
+    ) -> Result<()> {
+        let response: Value = self
+            .client
+            .request(method, (op.to_rpc(version), entry_point, U256::from(chain_id), json!({})))
+            .await?;
+        op.apply_paymaster_response(&response)
+    }
+}
+
+impl BlockchainService {
+    /// Address of the SimpleAccount `owner` gets for `salt`, whether or not it
+    /// has been deployed yet.
+    pub async fn get_smart_account_address(
+        &self,
+        owner: &str,
+        salt: U256,
+        version: EntryPointVersion,
+    ) -> Result<Address> {
+        let factory = SimpleAccountFactory::new(self.account_factory(version).await?, self.ethereum_client().await);
+        Ok(factory.get_address(owner.parse::<Address>()?, salt).call().await?)
+    }
+
+    /// Builds an operation making `owner`'s SimpleAccount call `to` (an
+    /// address or ENS name) with `value` and `data`, deploying the account
+    /// first if needed. Gas limits come from the bundler and, when
+    /// `paymaster_url` is configured, gas is sponsored by the paymaster. The
+    /// result still needs `sign_user_operation`.
+    pub async fn build_user_operation(
+        &self,
+        owner: &str,
+        salt: U256,
+        to: &str,
+        value: U256,
+        data: Bytes,
+        version: EntryPointVersion,
+    ) -> Result<UserOperation> {
+        let config = self.app.get_config().await;
+        let client = self.ethereum_client().await;
+        let entry_point = self.entry_point(version).await?;
+        let factory_address = self.account_factory(version).await?;
+        let owner = owner.parse::<Address>()?;
+
+        let factory = SimpleAccountFactory::new(factory_address, client.clone());
+        let sender = factory.get_address(owner, salt).call().await?;
+        let deployed = !client.get_code(sender, None).await?.is_empty();
+        let (max_fee_per_gas, max_priority_fee_per_gas) = client.estimate_eip1559_fees(None).await?;
+
+        let mut op = UserOperation {
+            sender,
+            nonce: EntryPoint::new(entry_point, client.clone())
+                .get_nonce(sender, U256::zero())
+                .call()
+                .await?,
+            factory: (!deployed).then_some(factory_address),
+            factory_data: if deployed {
+                Bytes::new()
+            } else {
+                factory
+                    .create_account(owner, salt)
+                    .calldata()
+                    .ok_or_else(|| anyhow!("Failed to encode createAccount"))?
+            },
+            call_data: SimpleAccount::new(sender, client.clone())
+                .execute(self.ens.resolve_recipient(to).await?.parse::<Address>()?, value, data)
+                .calldata()
+                .ok_or_else(|| anyhow!("Failed to encode execute"))?,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            signature: dummy_signature(),
+            ..Default::default()
+        };
+
+        let chain_id = self.ethereum_chain_id().await?;
+        let paymaster = match &config.blockchain.paymaster_url {
+            Some(url) => Some(BundlerClient::new(url)?),
+            None => None,
+        };
+        if let Some(paymaster) = &paymaster {
+            paymaster.paymaster_stub_data(&mut op, version, entry_point, chain_id).await?;
+        }
+
+        BundlerClient::new(&config.blockchain.bundler_url)?
+            .estimate_user_operation_gas(&op, version, entry_point)
+            .await?
+            .apply(&mut op);
+
+        if let Some(paymaster) = &paymaster {
+            paymaster.paymaster_data(&mut op, version, entry_point, chain_id).await?;
+        }
+        Ok(op)
+    }
+
+    /// Signs `op` as its SimpleAccount expects: an EIP-191 signature by the
+    /// owner over the user operation hash. Fails unless `owner` owns
+    /// `op.sender`.
+    pub async fn sign_user_operation(
+        &self,
+        op: &mut UserOperation,
+        version: EntryPointVersion,
+        owner: &dyn Signer,
+    ) -> Result<()> {
+        let account_owner = self.account_owner(op, version).await?;
+        if owner.address().parse::<Address>()? != account_owner {
+            bail!("Signer {} does not own account {:?}", owner.address(), op.sender);
+        }
+
+        let chain_id = self.ethereum_chain_id().await?;
+        let hash = op.hash(version, self.entry_point(version).await?, chain_id);
+        op.signature = owner.sign_message(hash.as_bytes()).await?.into();
+        Ok(())
+    }
+
+    /// Submits a signed operation to the configured bundler and returns its
+    /// user operation hash. The call it makes is checked against the owner's
+    /// spending policy first, with its maximum gas cost as the fee.
+    pub async fn send_user_operation(&self, op: &UserOperation, version: EntryPointVersion) -> Result<H256> {
+        let call = ExecuteCall::decode(&op.call_data)
+            .map_err(|_| anyhow!("User operation does not call SimpleAccount.execute"))?;
+        let request = TransactionRequest {
+            from: format!("{:?}", self.account_owner(op, version).await?),
+            to: format!("{:?}", call.dest),
+            amount: ethers::utils::format_units(call.value, "ether")?.parse::<f64>()?,
+            chain_type: ChainType::Ethereum,
+            gas_limit: None,
+            gas_price: None,
+            data: (!call.func.is_empty()).then(|| call.func.to_vec()),
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: None,
+        };
+        let fee = ethers::utils::format_units(op.max_gas_cost(version), "ether")?.parse::<f64>()?;
+        let reservation = self.authorize_spend(&request, fee, false).await?;
+
+        let config = self.app.get_config().await;
+        let sent = match BundlerClient::new(&config.blockchain.bundler_url) {
+            Ok(bundler) => {
+                bundler
+                    .send_user_operation(op, version, self.entry_point(version).await?)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        self.settle_spend(reservation, sent).await
+    }
+
+    pub async fn get_user_operation_receipt(&self, hash: H256) -> Result<Option<Value>> {
+        let config = self.app.get_config().await;
+        BundlerClient::new(&config.blockchain.bundler_url)?
+            .get_user_operation_receipt(hash)
+            .await
+    }
+
+    /// The owner `op.sender` was derived from: read from the `createAccount`
+    /// call for accounts still to be deployed, else from the account itself.
+    async fn account_owner(&self, op: &UserOperation, version: EntryPointVersion) -> Result<Address> {
+        let client = self.ethereum_client().await;
+        if op.factory.is_none() {
+            return Ok(SimpleAccount::new(op.sender, client).owner().call().await?);
+        }
+
+        if op.factory != Some(self.account_factory(version).await?) {
+            bail!("User operation deploys its account with an unknown factory");
+        }
+        let create = CreateAccountCall::decode(&op.factory_data)
+            .map_err(|_| anyhow!("User operation factory data is not a createAccount call"))?;
+        let factory = SimpleAccountFactory::new(self.account_factory(version).await?, client);
+        if factory.get_address(create.owner, create.salt).call().await? != op.sender {
+            bail!("Account {:?} is not derived from owner {:?}", op.sender, create.owner);
+        }
+        Ok(create.owner)
+    }
+
+    async fn entry_point(&self, version: EntryPointVersion) -> Result<Address> {
+        let config = self.app.get_config().await;
+        Ok(match version {
+            EntryPointVersion::V06 => config.blockchain.entry_point_v06_address.parse()?,
+            EntryPointVersion::V07 => config.blockchain.entry_point_v07_address.parse()?,
+        })
+    }
+
+    async fn account_factory(&self, version: EntryPointVersion) -> Result<Address> {
+        let config = self.app.get_config().await;
+        Ok(match version {
+            EntryPointVersion::V06 => config.blockchain.simple_account_factory_v06_address.parse()?,
+            EntryPointVersion::V07 => config.blockchain.simple_account_factory_v07_address.parse()?,
+        })
+    }
+}
+
+/// A well-formed signature that recovers to no owner, for gas estimation:
+/// SimpleAccount reports it as a failed validation rather than reverting.
+fn dummy_signature() -> Bytes {
+    let mut signature = [0u8; 65];
+    signature[..15].fill(0xff);
+    signature[15] = 0xf0;
+    signature[32] = 0x7a;
+    signature[33..64].fill(0xaa);
+    signature[64] = 0x1c;
+    signature.to_vec().into()
+}
+
+fn u128_bytes(value: U256) -> [u8; 16] {
+    let mut word = [0u8; 32];
+    value.to_big_endian(&mut word);
+    word[16..].try_into().unwrap()
+}
+
+/// Two 128-bit values packed into one 32-byte word, `high` first.
+fn pack_u128s(high: U256, low: U256) -> Vec<u8> {
+    [u128_bytes(high), u128_bytes(low)].concat()
+}
+
+/// Parses a JSON-RPC quantity, which bundlers send as hex strings or numbers.
+fn quantity(value: &Value) -> Result<U256> {
+    match value {
+        Value::String(s) => match s.strip_prefix("0x") {
+            Some(hex) => Ok(U256::from_str_radix(hex, 16)?),
+            None => Ok(U256::from_dec_str(s)?),
+        },
+        Value::Number(n) => n
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| anyhow!("Invalid quantity {}", n)),
+        other => Err(anyhow!("Invalid quantity {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::signing;
+    use ethers::{types::Signature, utils::hash_message};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    const COW_KEY: &str = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4";
+
+    fn user_operation() -> UserOperation {
+        UserOperation {
+            sender: Address::repeat_byte(0x11),
+            nonce: U256::from(7),
+            factory: Some(Address::repeat_byte(0x22)),
+            factory_data: vec![0xab, 0xcd].into(),
+            call_data: vec![0x01].into(),
+            call_gas_limit: U256::from(100_000),
+            verification_gas_limit: U256::from(200_000),
+            pre_verification_gas: U256::from(50_000),
+            max_fee_per_gas: U256::from(30_000_000_000u64),
+            max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+            signature: dummy_signature(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_user_operation_encoding() {
+        let mut op = user_operation();
+        assert_eq!(op.init_code().len(), 22);
+        assert_eq!(pack_u128s(op.verification_gas_limit, op.call_gas_limit)[15], 0);
+        assert_eq!(
+            U256::from_big_endian(&pack_u128s(op.verification_gas_limit, op.call_gas_limit)[16..]),
+            op.call_gas_limit
+        );
+
+        let v06 = op.to_rpc(EntryPointVersion::V06);
+        assert_eq!(v06["initCode"], format!("0x{}abcd", "22".repeat(20)));
+        assert_eq!(v06["paymasterAndData"], "0x");
+        let v07 = op.to_rpc(EntryPointVersion::V07);
+        assert!(v07.get("initCode").is_none());
+        assert!(v07.get("paymaster").is_none());
+
+        op.paymaster = Some(Address::repeat_byte(0x33));
+        op.paymaster_verification_gas_limit = U256::from(60_000);
+        assert_eq!(op.paymaster_and_data(EntryPointVersion::V06).len(), 20);
+        assert_eq!(op.paymaster_and_data(EntryPointVersion::V07).len(), 52);
+        let gas_price = op.max_fee_per_gas;
+        assert_eq!(op.max_gas_cost(EntryPointVersion::V06), U256::from(750_000) * gas_price);
+        assert_eq!(op.max_gas_cost(EntryPointVersion::V07), U256::from(410_000) * gas_price);
+
+        // The hash commits to the EntryPoint version, address and chain
+        let entry_point = Address::repeat_byte(0x44);
+        let hash = op.hash(EntryPointVersion::V07, entry_point, 1);
+        assert_ne!(hash, op.hash(EntryPointVersion::V06, entry_point, 1));
+        assert_ne!(hash, op.hash(EntryPointVersion::V07, entry_point, 11_155_111));
+
+        let key = hex::decode(COW_KEY).unwrap();
+        let signature = signing::sign_message(&key, hash.as_bytes()).unwrap();
+        let owner = ethers::signers::LocalWallet::from_bytes(&key).unwrap();
+        assert_eq!(
+            signature.recover(hash_message(hash.as_bytes())).unwrap(),
+            ethers::signers::Signer::address(&owner)
+        );
+        assert!(Signature::try_from(dummy_signature().as_ref()).is_ok());
+    }
+
+    /// Stand-in bundler answering JSON-RPC requests on one connection.
+    async fn serve_bundler(listener: TcpListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        loop {
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    return;
+                }
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0u8; content_length];
+            stream.read_exact(&mut body).await.unwrap();
+            let request: Value = serde_json::from_slice(&body).unwrap();
+
+            let result = match request["method"].as_str().unwrap() {
+                "eth_estimateUserOperationGas" => {
+                    assert!(request["params"][0]["factory"].is_string());
+                    json!({
+                        "preVerificationGas": "0xc350",
+                        "verificationGasLimit": "0x30d40",
+                        "callGasLimit": 100000,
+                    })
+                }
+                "eth_sendUserOperation" => json!(format!("0x{}", "ab".repeat(32))),
+                method => panic!("unexpected method {}", method),
+            };
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }).to_string();
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bundler_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_bundler(listener));
+
+        let bundler = BundlerClient::new(&url).unwrap();
+        let entry_point = Address::repeat_byte(0x44);
+        let mut op = user_operation();
+
+        let estimate = bundler
+            .estimate_user_operation_gas(&op, EntryPointVersion::V07, entry_point)
+            .await
+            .unwrap();
+        estimate.apply(&mut op);
+        assert_eq!(op.pre_verification_gas, U256::from(50_000));
+        assert_eq!(op.verification_gas_limit, U256::from(200_000));
+        assert_eq!(op.call_gas_limit, U256::from(100_000));
+
+        let hash = bundler
+            .send_user_operation(&op, EntryPointVersion::V07, entry_point)
+            .await
+            .unwrap();
+        assert_eq!(hash, H256::repeat_byte(0xab));
+    }
+}
@@ -1,54 +1,120 @@
-use crate::core::App;
-use anyhow::Result;
+pub mod approvals;
+pub mod bitcoin;
+pub mod ens;
+pub mod erc4337;
+pub mod offline;
+pub mod safe;
+mod solana;
+
+pub use crate::core::ChainType;
+
+use crate::core::{App, Chain, Network};
+use crate::policy::{Asset, Spend, SpendReservation};
+use crate::wallet::{normalize_address, signer::Signer, storage::WalletStore};
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use bitcoincore_rpc::{Auth, RpcApi};
 use ethers::{
-    providers::{Http, Provider, Ws},
-    types::{Address, Transaction, U256},
-};
-use solana_sdk::{
-    pubkey::Pubkey,
-    signature::{Keypair, Signer},
-    transaction::Transaction as SolanaTransaction,
+    providers::{Http, Middleware, Provider, Ws},
+    types::{Address, U256},
 };
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{OnceCell, RwLock};
 
 pub struct BlockchainService {
     app: Arc<App>,
     ethereum_provider: Arc<RwLock<Provider<Http>>>,
     solana_provider: Arc<RwLock<solana_client::rpc_client::RpcClient>>,
+    bitcoin_provider: Arc<RwLock<bitcoincore_rpc::Client>>,
+    bitcoin_watcher: bitcoin::BitcoinWatcher,
+    ethereum_chain: Chain,
+    /// Chain ID reported by the Ethereum node, once checked against
+    /// `ethereum_chain`.
+    ethereum_chain_id: OnceCell<u64>,
+    bitcoin_network: Network,
+    ens: ens::EnsResolver,
+    proposals: approvals::ProposalStore,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionRequest {
     pub from: String,
+    /// Recipient address; an ENS name for Ethereum, resolved before sending.
     pub to: String,
     pub amount: f64,
     pub chain_type: ChainType,
     pub gas_limit: Option<u64>,
     pub gas_price: Option<u64>,
-}
-
-#[derive(Debug, Clone)]
-pub enum ChainType {
-    Ethereum,
-    Solana,
+    /// Ethereum calldata for contract calls. When set and `gas_limit` is not,
+    /// the gas limit is estimated instead of assuming a plain transfer.
+    pub data: Option<Vec<u8>>,
+    /// Solana compute unit limit; estimated by simulation when unset.
+    pub compute_unit_limit: Option<u32>,
+    /// Solana priority fee in micro-lamports per compute unit; estimated from
+    /// recent prioritization fees when unset.
+    pub compute_unit_price: Option<u64>,
+    /// Solana durable nonce account to use instead of a recent blockhash, so
+    /// the signed transaction does not expire.
+    pub nonce_account: Option<String>,
+    /// Authority of `nonce_account`; must be `from` if set, as transfers
+    /// are signed by the sender alone.
+    pub nonce_authority: Option<String>,
+    /// Bitcoin fee rate in sat/vB; estimated by the node when unset.
+    pub fee_rate: Option<u64>,
 }
 
 impl BlockchainService {
     pub async fn new(app: Arc<App>) -> Result<Self> {
         let config = app.get_config().await;
-        
+        let ethereum_chain = config.blockchain.chain(ChainType::Ethereum)?;
+        config.blockchain.chain(ChainType::Solana)?;
+        let bitcoin_network = config.blockchain.chain(ChainType::Bitcoin)?.network;
+
         let ethereum_provider = Provider::<Http>::try_from(&config.blockchain.ethereum_rpc_url)?;
         let solana_provider = solana_client::rpc_client::RpcClient::new(config.blockchain.solana_rpc_url);
+        let bitcoin_auth = Auth::UserPass(config.blockchain.bitcoin_rpc_user, config.blockchain.bitcoin_rpc_password);
+        let bitcoin_provider = bitcoincore_rpc::Client::new(&config.blockchain.bitcoin_rpc_url, bitcoin_auth.clone())?;
+        let bitcoin_watcher = bitcoin::BitcoinWatcher::new(&config.blockchain.bitcoin_rpc_url, bitcoin_auth)?;
+        let ens = ens::EnsResolver::new(ethereum_provider.clone(), &config.blockchain.ens_registry_address)?;
+        let store = WalletStore::connect(&config.wallet.database_url).await?;
+        let proposals = approvals::ProposalStore::new(store.pool()).await?;
+        app.policy_engine().load(store.pool()).await?;
+
+        // A node on the wrong network fails startup; one that can't be
+        // reached yet is checked on first use instead
+        let ethereum_chain_id = OnceCell::new();
+        if let Ok(reported) = ethereum_provider.get_chainid().await {
+            ethereum_chain_id.set(check_chain_id(ethereum_chain, reported.as_u64())?)?;
+        }
 
         Ok(Self {
             app,
             ethereum_provider: Arc::new(RwLock::new(ethereum_provider)),
             solana_provider: Arc::new(RwLock::new(solana_provider)),
+            bitcoin_provider: Arc::new(RwLock::new(bitcoin_provider)),
+            bitcoin_watcher,
+            ethereum_chain,
+            ethereum_chain_id,
+            bitcoin_network,
+            ens,
+            proposals,
         })
     }
 
+    /// The EIP-155 chain ID of the configured Ethereum network, after
+    /// checking that the node is on it.
+    pub async fn ethereum_chain_id(&self) -> Result<u64> {
+        self.ethereum_chain_id
+            .get_or_try_init(|| async {
+                let reported = self.ethereum_provider.read().await.get_chainid().await?;
+                check_chain_id(self.ethereum_chain, reported.as_u64())
+            })
+            .await
+            .copied()
+    }
+
     pub async fn get_balance(&self, address: &str, chain_type: ChainType) -> Result<f64> {
         match chain_type {
             ChainType::Ethereum => {
@@ -63,50 +129,51 @@ impl BlockchainService {
                 let balance = provider.get_balance(&pubkey)?;
                 Ok(balance as f64 / 1e9) // Convert lamports to SOL
             }
+            ChainType::Bitcoin => {
+                let utxos = self.list_utxos(address).await?;
+                let balance: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+                Ok(balance as f64 / 1e8) // Convert satoshis to BTC
+            }
         }
     }
 
-    pub async fn send_transaction(&self, request: TransactionRequest) -> Result<String> {
-        match request.chain_type {
-            ChainType::Ethereum => {
-                let provider = self.ethereum_provider.read().await;
-                let from = request.from.parse::<Address>()?;
-                let to = request.to.parse::<Address>()?;
-                let amount = ethers::utils::parse_units(request.amount.to_string(), "ether")?;
-
-                let tx = Transaction::builder()
-                    .from(from)
-                    .to(to)
-                    .value(amount)
-                    .gas(request.gas_limit.unwrap_or(21000))
-                    .gas_price(request.gas_price.unwrap_or(1))
-                    .build();
-
-                let tx_hash = provider.send_transaction(tx, None).await?;
-                Ok(format!("0x{:x}", tx_hash))
-            }
-            ChainType::Solana => {
-                let provider = self.solana_provider.read().await;
-                let from = request.from.parse::<Pubkey>()?;
-                let to = request.to.parse::<Pubkey>()?;
-                let amount = (request.amount * 1e9) as u64; // Convert SOL to lamports
-
-                let recent_blockhash = provider.get_latest_blockhash()?;
-                let transaction = SolanaTransaction::new_signed_with_payer(
-                    &[solana_sdk::system_instruction::transfer(
-                        &from,
-                        &to,
-                        amount,
-                    )],
-                    Some(&from),
-                    &[&Keypair::new()], // This should be the actual keypair
-                    recent_blockhash,
-                );
-
-                let signature = provider.send_and_confirm_transaction(&transaction)?;
-                Ok(signature.to_string())
-            }
+    /// Prepares, signs and broadcasts `request` using `signer`, so the key
+    /// never has to be available to the blockchain service.
+    pub async fn send_transaction(&self, request: &TransactionRequest, signer: &dyn Signer) -> Result<String> {
+        self.send(request, signer, false).await
+    }
+
+    pub fn ens(&self) -> &ens::EnsResolver {
+        &self.ens
+    }
+
+    /// `request` with an ENS name in `to` replaced by the address it
+    /// resolves to.
+    pub async fn resolve_request(&self, request: &TransactionRequest) -> Result<TransactionRequest> {
+        let mut request = request.clone();
+        if request.chain_type == ChainType::Ethereum {
+            request.to = self.ens.resolve_recipient(&request.to).await?;
         }
+        Ok(request)
+    }
+
+    /// Sends `request` signed by `signer`; `approved` is set for proposals
+    /// that reached their quorum, which exempts them from the sender's
+    /// approval rule.
+    async fn send(&self, request: &TransactionRequest, signer: &dyn Signer, approved: bool) -> Result<String> {
+        let signer_address = normalize_address(signer.address(), &request.chain_type, self.bitcoin_network)?;
+        if signer_address != normalize_address(&request.from, &request.chain_type, self.bitcoin_network)? {
+            bail!("Signer {} cannot sign for {}", signer.address(), request.from);
+        }
+
+        let request = &self.resolve_request(request).await?;
+        let unsigned = self.export_unsigned_transaction(request).await?;
+        let reservation = self.authorize_spend(request, self.unsigned_fee(&unsigned).await?, approved).await?;
+        let sent = match signer.sign_transaction(&unsigned).await {
+            Ok(signed) => self.broadcast_signed_transaction(&signed).await,
+            Err(e) => Err(e),
+        };
+        self.settle_spend(reservation, sent).await
     }
 
     pub async fn get_transaction_status(&self, tx_hash: &str, chain_type: ChainType) -> Result<TransactionStatus> {
@@ -138,7 +205,57 @@ impl BlockchainService {
                     None => TransactionStatus::Pending,
                 })
             }
+            ChainType::Bitcoin => {
+                let provider = self.bitcoin_provider.read().await;
+                let txid = tx_hash.parse::<::bitcoin::Txid>()?;
+                let info = provider.get_raw_transaction_info(&txid, None)?;
+
+                Ok(match info.confirmations {
+                    Some(confirmations) if confirmations > 0 => TransactionStatus::Confirmed,
+                    _ => TransactionStatus::Pending,
+                })
+            }
+        }
+    }
+
+    /// Checks `request` against the sender's spending policy before anything
+    /// is signed, reserving it against the sender's limits until
+    /// `settle_spend`. `fee` is in native units.
+    pub(crate) async fn authorize_spend(
+        &self,
+        request: &TransactionRequest,
+        fee: f64,
+        approved: bool,
+    ) -> Result<SpendReservation> {
+        // Calldata makes `to` a contract being called; it is still the
+        // recipient of any value sent along, and of empty calldata
+        let data = request.data.as_deref().unwrap_or_default();
+        let recipient = (request.amount > 0.0 || data.is_empty()).then(|| request.to.clone());
+        let contracts = if data.is_empty() { Vec::new() } else { vec![request.to.clone()] };
+
+        let spend = Spend {
+            wallet: request.from.clone(),
+            chain_type: request.chain_type,
+            recipient,
+            asset: Asset::Native,
+            amount: request.amount,
+            fee: Some(fee),
+            contracts,
+            approved,
+        };
+        Ok(self.app.policy_engine().reserve(&spend).await?)
+    }
+
+    /// Commits `reservation` if the transaction was sent and releases it
+    /// otherwise, so failed sends don't count towards the sender's limits.
+    pub(crate) async fn settle_spend<T>(&self, reservation: SpendReservation, sent: Result<T>) -> Result<T> {
+        let policy_engine = self.app.policy_engine();
+        if sent.is_ok() {
+            policy_engine.commit(reservation);
+        } else {
+            policy_engine.release(reservation).await;
         }
+        sent
     }
 
     pub async fn run(&self) -> Result<()> {
@@ -147,6 +264,20 @@ impl BlockchainService {
     }
 }
 
+/// Returns `reported` if it is the chain ID `chain` should have.
+fn check_chain_id(chain: Chain, reported: u64) -> Result<u64> {
+    match chain.chain_id() {
+        Some(expected) if expected == reported => Ok(reported),
+        Some(expected) => bail!(
+            "Ethereum node is on chain {}, but {} is chain {}",
+            reported,
+            chain,
+            expected
+        ),
+        None => bail!("{} has no chain ID", chain),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum TransactionStatus {
     Pending,
@@ -157,6 +288,7 @@ pub enum TransactionStatus {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::policy::SpendingPolicy;
 
     #[tokio::test]
     async fn test_blockchain_service_initialization() {
@@ -164,4 +296,50 @@ mod tests {
         let service = BlockchainService::new(app).await.unwrap();
         assert!(service.ethereum_provider.read().await.as_ref().is_some());
     }
+
+    #[tokio::test]
+    async fn test_calldata_does_not_bypass_recipient_rules() {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = "sqlite::memory:".to_string();
+        config.blockchain.ethereum_rpc_url = "http://127.0.0.1:1".to_string();
+        let service = BlockchainService::new(Arc::new(App::from_config(config))).await.unwrap();
+
+        let from = "0x52908400098527886e0f7030069857d2e4169ee7";
+        let denied = "0x8617e340b3d01fa5f11f306f4090fd50e238070d";
+        let policy = SpendingPolicy {
+            denied_recipients: vec![denied.to_string()],
+            ..Default::default()
+        };
+        service.app.policy_engine().set_policy(from, ChainType::Ethereum, policy).await.unwrap();
+
+        let mut request = TransactionRequest {
+            from: from.to_string(),
+            to: denied.to_string(),
+            amount: 0.0,
+            chain_type: ChainType::Ethereum,
+            gas_limit: None,
+            gas_price: None,
+            data: Some(Vec::new()),
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: None,
+        };
+        assert!(service.authorize_spend(&request, 0.0, false).await.is_err());
+
+        // A call with value pays the contract, and a call without value
+        // still targets a denied address
+        request.data = Some(vec![0xa9, 0x05, 0x9c, 0xbb]);
+        assert!(service.authorize_spend(&request, 0.0, false).await.is_err());
+        request.amount = 1.0;
+        assert!(service.authorize_spend(&request, 0.0, false).await.is_err());
+    }
+
+    #[test]
+    fn test_chain_id_check() {
+        let sepolia = Chain::new(ChainType::Ethereum, Network::Sepolia).unwrap();
+        assert_eq!(check_chain_id(sepolia, 11_155_111).unwrap(), 11_155_111);
+        assert!(check_chain_id(sepolia, 1).is_err());
+    }
 } 
\ No newline at end of file
@@ -0,0 +1,273 @@
+use super::{BlockchainService, ChainType, TransactionRequest};
+use anyhow::{anyhow, bail, Result};
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes},
+};
+use bitcoincore_rpc::RpcApi;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{message::Message, transaction::Transaction as SolanaTransaction};
+
+/// Version of the offline exchange format. Bump when the payload encoding changes.
+pub const OFFLINE_FORMAT_VERSION: u8 = 1;
+
+/// A transaction prepared on an online node, ready to be carried to an
+/// air-gapped machine for signing.
+///
+/// The payload is a JSON-encoded `TypedTransaction` for Ethereum, a
+/// bincode-encoded legacy `Message` for Solana and a serialized BIP-174 PSBT
+/// for Bitcoin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnsignedTransaction {
+    pub version: u8,
+    pub chain_type: ChainType,
+    pub from: String,
+    #[serde(with = "hex_bytes")]
+    pub payload: Vec<u8>,
+}
+
+/// A signed transaction carried back from the air-gapped machine. The
+/// payload is the raw transaction exactly as it is broadcast.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedTransaction {
+    pub version: u8,
+    pub chain_type: ChainType,
+    pub from: String,
+    #[serde(with = "hex_bytes")]
+    pub payload: Vec<u8>,
+}
+
+macro_rules! impl_offline_encoding {
+    ($ty:ty) => {
+        impl $ty {
+            pub fn to_json(&self) -> Result<String> {
+                Ok(serde_json::to_string_pretty(self)?)
+            }
+
+            pub fn from_json(json: &str) -> Result<Self> {
+                let decoded: Self = serde_json::from_str(json)?;
+                check_version(decoded.version)?;
+                Ok(decoded)
+            }
+
+            /// Compact binary encoding suited to QR codes.
+            pub fn to_bytes(&self) -> Vec<u8> {
+                encode_binary(self.version, self.chain_type, &self.from, &self.payload)
+            }
+
+            pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+                let (version, chain_type, from, payload) = decode_binary(bytes)?;
+                Ok(Self { version, chain_type, from, payload })
+            }
+        }
+    };
+}
+
+impl_offline_encoding!(UnsignedTransaction);
+impl_offline_encoding!(SignedTransaction);
+
+impl BlockchainService {
+    /// Prepares `request` for offline signing, filling in the nonce, chain ID
+    /// and fee fields from the network. Solana transfers that may sit for more
+    /// than a minute before broadcast should set `request.nonce_account`.
+    pub async fn export_unsigned_transaction(&self, request: &TransactionRequest) -> Result<UnsignedTransaction> {
+        let request = &self.resolve_request(request).await?;
+        let payload = match request.chain_type {
+            ChainType::Ethereum => {
+                let chain_id = self.ethereum_chain_id().await?;
+                let provider = self.ethereum_provider.read().await;
+                let from = request.from.parse::<Address>()?;
+                let to = request.to.parse::<Address>()?;
+                let amount = ethers::utils::parse_units(request.amount.to_string(), "ether")?;
+
+                let nonce = provider.get_transaction_count(from, None).await?;
+                let gas_price = match request.gas_price {
+                    Some(gas_price) => gas_price.into(),
+                    None => provider.get_gas_price().await?,
+                };
+
+                let mut tx: TypedTransaction = ethers::types::TransactionRequest::new()
+                    .from(from)
+                    .to(to)
+                    .value(amount)
+                    .gas_price(gas_price)
+                    .nonce(nonce)
+                    .chain_id(chain_id)
+                    .into();
+                if let Some(data) = &request.data {
+                    tx.set_data(Bytes::from(data.clone()));
+                }
+                let gas = match (request.gas_limit, &request.data) {
+                    (Some(gas_limit), _) => gas_limit.into(),
+                    (None, Some(_)) => provider.estimate_gas(&tx, None).await?,
+                    (None, None) => 21000.into(),
+                };
+                tx.set_gas(gas);
+
+                serde_json::to_vec(&tx)?
+            }
+            ChainType::Solana => {
+                let transaction = self.build_solana_transfer(request).await?;
+                bincode::serialize(&transaction.message)?
+            }
+            ChainType::Bitcoin => {
+                let amount = (request.amount * 1e8).round() as u64; // Convert BTC to satoshis
+                self.create_psbt(&request.from, &request.to, amount, request.fee_rate)
+                    .await?
+                    .serialize()
+            }
+        };
+
+        Ok(UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: request.chain_type,
+            from: request.from.clone(),
+            payload,
+        })
+    }
+
+    /// Broadcasts a transaction signed offline and returns its hash or signature.
+    pub async fn broadcast_signed_transaction(&self, signed: &SignedTransaction) -> Result<String> {
+        check_version(signed.version)?;
+
+        match signed.chain_type {
+            ChainType::Ethereum => {
+                let provider = self.ethereum_provider.read().await;
+                let pending = provider
+                    .send_raw_transaction(Bytes::from(signed.payload.clone()))
+                    .await?;
+                Ok(format!("0x{:x}", pending.tx_hash()))
+            }
+            ChainType::Solana => {
+                let transaction: SolanaTransaction = bincode::deserialize(&signed.payload)?;
+                transaction.verify()?;
+                self.send_solana_transaction(&transaction).await
+            }
+            ChainType::Bitcoin => {
+                let transaction: ::bitcoin::Transaction = ::bitcoin::consensus::deserialize(&signed.payload)?;
+                let provider = self.bitcoin_provider.read().await;
+                Ok(provider.send_raw_transaction(&transaction)?.to_string())
+            }
+        }
+    }
+}
+
+impl BlockchainService {
+    /// The fee `unsigned` pays, in native units.
+    pub(crate) async fn unsigned_fee(&self, unsigned: &UnsignedTransaction) -> Result<f64> {
+        match unsigned.chain_type {
+            ChainType::Ethereum => {
+                let tx: TypedTransaction = serde_json::from_slice(&unsigned.payload)?;
+                let gas = tx.gas().copied().unwrap_or_default();
+                let gas_price = tx.gas_price().unwrap_or_default();
+                Ok(ethers::utils::format_units(gas * gas_price, "ether")?.parse::<f64>()?)
+            }
+            ChainType::Solana => {
+                let message: Message = bincode::deserialize(&unsigned.payload)?;
+                let fee = self.solana_provider.read().await.get_fee_for_message(&message)?;
+                Ok(fee as f64 / 1e9) // Convert lamports to SOL
+            }
+            ChainType::Bitcoin => {
+                let psbt = ::bitcoin::psbt::Psbt::deserialize(&unsigned.payload)?;
+                Ok(super::bitcoin::psbt_fee(&psbt)? as f64 / 1e8) // Convert satoshis to BTC
+            }
+        }
+    }
+}
+
+fn check_version(version: u8) -> Result<()> {
+    if version != OFFLINE_FORMAT_VERSION {
+        bail!("Unsupported offline transaction format version {}", version);
+    }
+    Ok(())
+}
+
+fn chain_tag(chain_type: ChainType) -> u8 {
+    match chain_type {
+        ChainType::Ethereum => 0,
+        ChainType::Solana => 1,
+        ChainType::Bitcoin => 2,
+    }
+}
+
+fn chain_from_tag(tag: u8) -> Result<ChainType> {
+    match tag {
+        0 => Ok(ChainType::Ethereum),
+        1 => Ok(ChainType::Solana),
+        2 => Ok(ChainType::Bitcoin),
+        other => Err(anyhow!("Unknown chain tag {}", other)),
+    }
+}
+
+/// Layout: version (1) | chain (1) | from length (2, BE) | from | payload length (4, BE) | payload.
+fn encode_binary(version: u8, chain_type: ChainType, from: &str, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + from.len() + payload.len());
+    bytes.push(version);
+    bytes.push(chain_tag(chain_type));
+    bytes.extend_from_slice(&(from.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(from.as_bytes());
+    bytes.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(payload);
+    bytes
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<(u8, ChainType, String, Vec<u8>)> {
+    let truncated = || anyhow!("Truncated offline transaction");
+
+    let (&version, rest) = bytes.split_first().ok_or_else(truncated)?;
+    check_version(version)?;
+    let (&chain, rest) = rest.split_first().ok_or_else(truncated)?;
+    let chain_type = chain_from_tag(chain)?;
+
+    if rest.len() < 2 {
+        return Err(truncated());
+    }
+    let (len, rest) = rest.split_at(2);
+    let from_len = u16::from_be_bytes([len[0], len[1]]) as usize;
+    if rest.len() < from_len + 4 {
+        return Err(truncated());
+    }
+    let (from, rest) = rest.split_at(from_len);
+    let (len, rest) = rest.split_at(4);
+    let payload_len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+    if rest.len() != payload_len {
+        return Err(anyhow!("Offline transaction payload length mismatch"));
+    }
+
+    Ok((version, chain_type, String::from_utf8(from.to_vec())?, rest.to_vec()))
+}
+
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        hex::decode(s.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_encoding_roundtrip() {
+        let unsigned = UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: ChainType::Solana,
+            from: "4Nd1mYQzvgV8Vr3Z3nYb7pD9T8gG8M1wRk3S6hT2xXyZ".to_string(),
+            payload: vec![1, 2, 3, 4],
+        };
+
+        let json = unsigned.to_json().unwrap();
+        assert_eq!(UnsignedTransaction::from_json(&json).unwrap(), unsigned);
+
+        let bytes = unsigned.to_bytes();
+        assert_eq!(UnsignedTransaction::from_bytes(&bytes).unwrap(), unsigned);
+        assert!(UnsignedTransaction::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
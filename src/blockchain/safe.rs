@@ -0,0 +1,404 @@
+use super::{BlockchainService, ChainType, TransactionRequest};
+use crate::wallet::signer::Signer;
+use anyhow::{anyhow, bail, Result};
+use ethers::{
+    contract::{abigen, EthLogDecode},
+    providers::{Middleware, PendingTransaction},
+    types::{transaction::eip712::TypedData, Address, Bytes, Signature, H256, U256},
+};
+use std::sync::Arc;
+
+abigen!(
+    GnosisSafe,
+    r#"[
+        function getOwners() external view returns (address[])
+        function getThreshold() external view returns (uint256)
+        function nonce() external view returns (uint256)
+        function setup(address[] owners, uint256 threshold, address to, bytes data, address fallbackHandler, address paymentToken, uint256 payment, address paymentReceiver) external
+        function execTransaction(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, bytes signatures) external payable returns (bool)
+    ]"#
+);
+
+abigen!(
+    SafeProxyFactory,
+    r#"[
+        function createProxyWithNonce(address singleton, bytes initializer, uint256 saltNonce) external returns (address)
+        event ProxyCreation(address proxy, address singleton)
+    ]"#
+);
+
+/// On-chain state of a Safe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeInfo {
+    pub owners: Vec<Address>,
+    pub threshold: u64,
+    pub nonce: U256,
+}
+
+/// A Safe transaction (`SafeTx`) awaiting owner signatures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafeTransaction {
+    pub to: Address,
+    pub value: U256,
+    pub data: Bytes,
+    /// 0 for `CALL`, 1 for `DELEGATECALL`.
+    pub operation: u8,
+    pub safe_tx_gas: U256,
+    pub base_gas: U256,
+    pub gas_price: U256,
+    pub gas_token: Address,
+    pub refund_receiver: Address,
+    pub nonce: U256,
+}
+
+impl SafeTransaction {
+    /// A plain call from the Safe with no gas refund.
+    pub fn call(to: Address, value: U256, data: Bytes, nonce: U256) -> Self {
+        Self {
+            to,
+            value,
+            data,
+            operation: 0,
+            safe_tx_gas: U256::zero(),
+            base_gas: U256::zero(),
+            gas_price: U256::zero(),
+            gas_token: Address::zero(),
+            refund_receiver: Address::zero(),
+            nonce,
+        }
+    }
+
+    /// The EIP-712 typed data owners sign, using the Safe >= 1.3.0 domain.
+    pub fn typed_data(&self, chain_id: u64, safe: Address) -> Result<TypedData> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "SafeTx": [
+                    { "name": "to", "type": "address" },
+                    { "name": "value", "type": "uint256" },
+                    { "name": "data", "type": "bytes" },
+                    { "name": "operation", "type": "uint8" },
+                    { "name": "safeTxGas", "type": "uint256" },
+                    { "name": "baseGas", "type": "uint256" },
+                    { "name": "gasPrice", "type": "uint256" },
+                    { "name": "gasToken", "type": "address" },
+                    { "name": "refundReceiver", "type": "address" },
+                    { "name": "nonce", "type": "uint256" }
+                ]
+            },
+            "primaryType": "SafeTx",
+            "domain": {
+                "chainId": chain_id,
+                "verifyingContract": safe,
+            },
+            "message": {
+                "to": self.to,
+                "value": self.value.to_string(),
+                "data": self.data,
+                "operation": self.operation,
+                "safeTxGas": self.safe_tx_gas.to_string(),
+                "baseGas": self.base_gas.to_string(),
+                "gasPrice": self.gas_price.to_string(),
+                "gasToken": self.gas_token,
+                "refundReceiver": self.refund_receiver,
+                "nonce": self.nonce.to_string(),
+            }
+        }))?)
+    }
+
+    /// The `SafeTx` hash, as returned by the Safe's `getTransactionHash`.
+    pub fn hash(&self, chain_id: u64, safe: Address) -> Result<H256> {
+        use ethers::types::transaction::eip712::Eip712;
+        Ok(H256::from(self.typed_data(chain_id, safe)?.encode_eip712()?))
+    }
+}
+
+impl BlockchainService {
+    /// Reads a Safe's owners, threshold and current nonce.
+    pub async fn get_safe_info(&self, safe: &str) -> Result<SafeInfo> {
+        let safe = GnosisSafe::new(safe.parse::<Address>()?, self.ethereum_client().await);
+
+        Ok(SafeInfo {
+            owners: safe.get_owners().call().await?,
+            threshold: safe.get_threshold().call().await?.as_u64(),
+            nonce: safe.nonce().call().await?,
+        })
+    }
+
+    /// Builds a call from `safe` at its current nonce.
+    pub async fn build_safe_transaction(
+        &self,
+        safe: &str,
+        to: &str,
+        value: U256,
+        data: Bytes,
+    ) -> Result<SafeTransaction> {
+        let info = self.get_safe_info(safe).await?;
+        Ok(SafeTransaction::call(to.parse::<Address>()?, value, data, info.nonce))
+    }
+
+    /// Collects EIP-712 signatures over `tx` from `owners` and packs them in
+    /// the ascending owner order `execTransaction` expects. Fails unless the
+    /// signers are owners and reach the Safe's threshold.
+    pub async fn collect_safe_signatures(
+        &self,
+        safe: &str,
+        tx: &SafeTransaction,
+        owners: &[&dyn Signer],
+    ) -> Result<Bytes> {
+        let info = self.get_safe_info(safe).await?;
+        let safe_address = safe.parse::<Address>()?;
+        let chain_id = self.ethereum_chain_id().await?;
+        let typed_data = tx.typed_data(chain_id, safe_address)?;
+
+        let mut signatures = Vec::with_capacity(owners.len());
+        for owner in owners {
+            let owner_address = owner.address().parse::<Address>()?;
+            if !info.owners.contains(&owner_address) {
+                bail!("{} is not an owner of Safe {}", owner.address(), safe);
+            }
+            // The Safe rejects repeated owners, and they must not count twice
+            // towards the threshold
+            if signatures.iter().any(|(address, _)| *address == owner_address) {
+                bail!("{} signed more than once", owner.address());
+            }
+            signatures.push((owner_address, owner.sign_typed_data(&typed_data).await?));
+        }
+
+        if (signatures.len() as u64) < info.threshold {
+            bail!(
+                "Safe {} needs {} signatures, only {} collected",
+                safe,
+                info.threshold,
+                signatures.len()
+            );
+        }
+
+        Ok(pack_signatures(signatures))
+    }
+
+    /// Submits `execTransaction` for `tx`, paid for and sent by `executor`.
+    pub async fn execute_safe_transaction(
+        &self,
+        safe: &str,
+        tx: &SafeTransaction,
+        signatures: Bytes,
+        executor: &dyn Signer,
+    ) -> Result<String> {
+        let contract = GnosisSafe::new(safe.parse::<Address>()?, self.ethereum_client().await);
+        let calldata = contract
+            .exec_transaction(
+                tx.to,
+                tx.value,
+                tx.data.clone(),
+                tx.operation,
+                tx.safe_tx_gas,
+                tx.base_gas,
+                tx.gas_price,
+                tx.gas_token,
+                tx.refund_receiver,
+                signatures,
+            )
+            .calldata()
+            .ok_or_else(|| anyhow!("Failed to encode execTransaction"))?;
+
+        self.send_contract_call(executor, safe, calldata).await
+    }
+
+    /// Deploys a new Safe proxy through the configured proxy factory and
+    /// returns its address once the deployment is mined.
+    pub async fn deploy_safe(
+        &self,
+        owners: &[String],
+        threshold: u64,
+        salt_nonce: U256,
+        deployer: &dyn Signer,
+    ) -> Result<String> {
+        if threshold == 0 || threshold as usize > owners.len() {
+            bail!("Threshold must be between 1 and the number of owners");
+        }
+
+        let config = self.app.get_config().await;
+        let singleton = config.blockchain.safe_singleton_address.parse::<Address>()?;
+        let factory_address = config.blockchain.safe_proxy_factory_address.parse::<Address>()?;
+        let fallback_handler = config.blockchain.safe_fallback_handler_address.parse::<Address>()?;
+        let owners = owners
+            .iter()
+            .map(|owner| owner.parse::<Address>())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let client = self.ethereum_client().await;
+        let initializer = GnosisSafe::new(singleton, client.clone())
+            .setup(
+                owners,
+                threshold.into(),
+                Address::zero(),
+                Bytes::new(),
+                fallback_handler,
+                Address::zero(),
+                U256::zero(),
+                Address::zero(),
+            )
+            .calldata()
+            .ok_or_else(|| anyhow!("Failed to encode Safe setup"))?;
+        let calldata = SafeProxyFactory::new(factory_address, client.clone())
+            .create_proxy_with_nonce(singleton, initializer, salt_nonce)
+            .calldata()
+            .ok_or_else(|| anyhow!("Failed to encode createProxyWithNonce"))?;
+
+        let tx_hash = self
+            .send_contract_call(deployer, &format!("{:?}", factory_address), calldata)
+            .await?;
+        let receipt = PendingTransaction::new(tx_hash.parse::<H256>()?, client.provider())
+            .await?
+            .ok_or_else(|| anyhow!("Safe deployment {} was dropped", tx_hash))?;
+
+        receipt
+            .logs
+            .iter()
+            .filter(|log| log.address == factory_address)
+            .find_map(|log| ProxyCreationFilter::decode_log(&log.clone().into()).ok())
+            .map(|event| format!("{:?}", event.proxy))
+            .ok_or_else(|| anyhow!("Safe deployment {} emitted no ProxyCreation event", tx_hash))
+    }
+
+    pub(crate) async fn ethereum_client(&self) -> Arc<ethers::providers::Provider<ethers::providers::Http>> {
+        Arc::new(self.ethereum_provider.read().await.clone())
+    }
+
+    async fn send_contract_call(&self, signer: &dyn Signer, to: &str, calldata: Bytes) -> Result<String> {
+        let request = TransactionRequest {
+            from: signer.address().to_string(),
+            to: to.to_string(),
+            amount: 0.0,
+            chain_type: ChainType::Ethereum,
+            gas_limit: None,
+            gas_price: None,
+            data: Some(calldata.to_vec()),
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: None,
+        };
+        self.send_transaction(&request, signer).await
+    }
+}
+
+/// Concatenates 65-byte `r || s || v` signatures sorted by owner address.
+pub fn pack_signatures(mut signatures: Vec<(Address, Signature)>) -> Bytes {
+    signatures.sort_by_key(|(owner, _)| *owner);
+    signatures
+        .into_iter()
+        .flat_map(|(_, signature)| signature.to_vec())
+        .collect::<Vec<u8>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{App, Network};
+    use crate::wallet::{signing::sign_typed_data, WalletService};
+    use ethers::signers::{LocalWallet, Signer as _};
+    use std::time::Duration;
+
+    #[test]
+    fn test_signatures_sorted_by_owner() {
+        let safe = "0x1111111111111111111111111111111111111111".parse::<Address>().unwrap();
+        let tx = SafeTransaction::call(Address::repeat_byte(0x22), U256::exp10(18), Bytes::new(), U256::from(3));
+        let typed_data = tx.typed_data(31337, safe).unwrap();
+        let hash = tx.hash(31337, safe).unwrap();
+
+        let mut signatures = Vec::new();
+        for key in [[1u8; 32], [2u8; 32], [3u8; 32]] {
+            let owner = LocalWallet::from_bytes(&key).unwrap().address();
+            signatures.push((owner, sign_typed_data(&key, &typed_data).unwrap()));
+        }
+
+        let packed = pack_signatures(signatures);
+        assert_eq!(packed.len(), 3 * 65);
+
+        let recovered: Vec<Address> = packed
+            .chunks(65)
+            .map(|chunk| Signature::try_from(chunk).unwrap().recover(hash).unwrap())
+            .collect();
+        let mut sorted = recovered.clone();
+        sorted.sort();
+        assert_eq!(recovered, sorted);
+    }
+
+    /// Gives `address` 100 ETH on anvil.
+    async fn fund(service: &BlockchainService, address: Address) {
+        service
+            .ethereum_client()
+            .await
+            .request::<_, ()>("anvil_setBalance", (address, U256::exp10(20)))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs anvil on localhost:8545 with the Safe v1.3.0 contracts deployed"]
+    async fn test_safe_on_anvil() {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = "sqlite::memory:".to_string();
+        config.wallet.encryption_key_source = "config".to_string();
+        config.blockchain.ethereum_rpc_url = "http://127.0.0.1:8545".to_string();
+        config.blockchain.ethereum_network = Network::Localnet;
+        let app = Arc::new(App::from_config(config));
+        let wallets = WalletService::new(app.clone()).await.unwrap();
+        wallets.unlock_from_config(Duration::from_secs(60)).await.unwrap();
+        let service = BlockchainService::new(app).await.unwrap();
+
+        let mut owners = Vec::new();
+        let mut signers = Vec::new();
+        for _ in 0..3 {
+            let wallet = wallets.create_wallet(ChainType::Ethereum).await.unwrap();
+            fund(&service, wallet.address.parse().unwrap()).await;
+            signers.push(wallets.signer(&wallet.address).await.unwrap());
+            owners.push(wallet.address);
+        }
+
+        let safe = service
+            .deploy_safe(&owners, 2, U256::from(rand::random::<u64>()), signers[0].as_ref())
+            .await
+            .unwrap();
+        let info = service.get_safe_info(&safe).await.unwrap();
+        assert_eq!(info.threshold, 2);
+        assert_eq!(info.nonce, U256::zero());
+        for owner in &owners {
+            assert!(info.owners.contains(&owner.parse().unwrap()));
+        }
+
+        // Move 1 ETH out of the Safe with two of the three owners
+        fund(&service, safe.parse().unwrap()).await;
+        let recipient = Address::random();
+        let tx = service
+            .build_safe_transaction(&safe, &format!("{:?}", recipient), U256::exp10(18), Bytes::new())
+            .await
+            .unwrap();
+        assert!(service
+            .collect_safe_signatures(&safe, &tx, &[signers[1].as_ref(), signers[1].as_ref()])
+            .await
+            .is_err());
+        let signatures = service
+            .collect_safe_signatures(&safe, &tx, &[signers[2].as_ref(), signers[1].as_ref()])
+            .await
+            .unwrap();
+        let tx_hash = service
+            .execute_safe_transaction(&safe, &tx, signatures, signers[0].as_ref())
+            .await
+            .unwrap();
+        PendingTransaction::new(tx_hash.parse().unwrap(), service.ethereum_client().await.provider())
+            .await
+            .unwrap();
+
+        let client = service.ethereum_client().await;
+        assert_eq!(client.get_balance(recipient, None).await.unwrap(), U256::exp10(18));
+        assert_eq!(service.get_safe_info(&safe).await.unwrap().nonce, U256::one());
+    }
+}
@@ -0,0 +1,548 @@
+use super::{BlockchainService, TransactionRequest};
+use anyhow::{anyhow, bail, Result};
+use solana_address_lookup_table_program::{instruction as lookup_table_instruction, state::AddressLookupTable};
+use solana_client::{nonce_utils, rpc_config::RpcSimulateTransactionConfig};
+use solana_sdk::{
+    address_lookup_table_account::AddressLookupTableAccount,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, Message, VersionedMessage},
+    nonce,
+    pubkey::Pubkey,
+    signature::Signer,
+    system_instruction,
+    transaction::{Transaction as SolanaTransaction, VersionedTransaction},
+};
+
+/// Upper bound the runtime accepts for a single transaction.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Headroom applied on top of the simulated compute unit consumption, in percent.
+const COMPUTE_UNIT_MARGIN_PERCENT: u64 = 10;
+
+/// Fixed headroom added after the percentage, so small transactions (a
+/// plain transfer consumes a few hundred units) are not left a handful of
+/// units short by consumption that varies between simulation and execution.
+const COMPUTE_UNIT_HEADROOM: u64 = 1_000;
+
+/// Percentile of recent prioritization fees used as the priority fee estimate.
+const PRIORITY_FEE_PERCENTILE: usize = 75;
+
+/// Addresses appended per extend instruction, keeping each transaction under
+/// the packet size limit.
+const LOOKUP_TABLE_EXTEND_CHUNK: usize = 20;
+
+impl BlockchainService {
+    /// Estimates a priority fee (micro-lamports per compute unit) from the
+    /// recent prioritization fees paid by transactions locking `accounts`.
+    pub async fn estimate_priority_fee(&self, accounts: &[Pubkey]) -> Result<u64> {
+        let provider = self.solana_provider.read().await;
+        let fees = provider.get_recent_prioritization_fees(accounts)?;
+
+        Ok(priority_fee_from_samples(
+            fees.into_iter().map(|fee| fee.prioritization_fee).collect(),
+        ))
+    }
+
+    /// Simulates `instructions` exactly as given and returns the compute
+    /// units they consume, including a safety margin. They should be the
+    /// final instruction list, with the compute unit limit set to
+    /// `MAX_COMPUTE_UNIT_LIMIT` so the estimate isn't capped.
+    pub async fn estimate_compute_units(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+    ) -> Result<u32> {
+        let transaction = SolanaTransaction::new_unsigned(Message::new(instructions, Some(payer)));
+        let provider = self.solana_provider.read().await;
+        let result = provider
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    ..Default::default()
+                },
+            )?
+            .value;
+
+        if let Some(err) = result.err {
+            return Err(anyhow!("Transaction simulation failed: {}", err));
+        }
+
+        let consumed = result
+            .units_consumed
+            .ok_or_else(|| anyhow!("Simulation did not report compute units consumed"))?;
+
+        Ok(compute_unit_limit_with_margin(consumed))
+    }
+
+    /// Prepends compute budget instructions to `instructions`, and the
+    /// advance-nonce instruction when `nonce` (account and authority) is set,
+    /// estimating the limit and price when the request doesn't specify them.
+    /// The limit is estimated by simulating that complete instruction list,
+    /// so the budget and nonce instructions' own units are counted.
+    pub(crate) async fn with_compute_budget(
+        &self,
+        instructions: Vec<Instruction>,
+        payer: &Pubkey,
+        request: &TransactionRequest,
+        nonce: Option<(&Pubkey, &Pubkey)>,
+    ) -> Result<Vec<Instruction>> {
+        let unit_price = match request.compute_unit_price {
+            Some(price) => price,
+            None => {
+                let accounts = writable_accounts(&instructions);
+                self.estimate_priority_fee(&accounts).await?
+            }
+        };
+
+        let assemble = |unit_limit| {
+            let budgeted = prepend_compute_budget(instructions.clone(), unit_limit, unit_price);
+            match nonce {
+                Some((nonce_account, authority)) => with_nonce_advance(budgeted, nonce_account, authority),
+                None => budgeted,
+            }
+        };
+
+        let unit_limit = match request.compute_unit_limit {
+            Some(limit) => limit,
+            None => {
+                self.estimate_compute_units(&assemble(MAX_COMPUTE_UNIT_LIMIT), payer)
+                    .await?
+            }
+        };
+        Ok(assemble(unit_limit))
+    }
+}
+
+impl BlockchainService {
+    /// Builds an unsigned SOL transfer for `request`, including compute budget
+    /// instructions. When `request.nonce_account` is set the transaction uses
+    /// the stored nonce instead of a recent blockhash, so it can be signed
+    /// offline and broadcast long after the blockhash would have expired.
+    pub async fn build_solana_transfer(&self, request: &TransactionRequest) -> Result<SolanaTransaction> {
+        let from = request.from.parse::<Pubkey>()?;
+        let to = request.to.parse::<Pubkey>()?;
+        let amount = (request.amount * 1e9) as u64; // Convert SOL to lamports
+        let transfer = vec![system_instruction::transfer(&from, &to, amount)];
+
+        let (instructions, blockhash) = match &request.nonce_account {
+            Some(nonce_account) => {
+                let nonce_account = nonce_account.parse::<Pubkey>()?;
+                // Transfers are signed by the sender's key alone, so another
+                // authority's signature could never be added
+                if let Some(authority) = &request.nonce_authority {
+                    if authority.parse::<Pubkey>()? != from {
+                        bail!("Nonce authority {} must be the sender {}", authority, from);
+                    }
+                }
+                let instructions = self
+                    .with_compute_budget(transfer, &from, request, Some((&nonce_account, &from)))
+                    .await?;
+                (instructions, self.get_nonce(&nonce_account).await?)
+            }
+            None => {
+                let instructions = self.with_compute_budget(transfer, &from, request, None).await?;
+                let provider = self.solana_provider.read().await;
+                (instructions, provider.get_latest_blockhash()?)
+            }
+        };
+
+        let mut message = Message::new(&instructions, Some(&from));
+        message.recent_blockhash = blockhash;
+        Ok(SolanaTransaction::new_unsigned(message))
+    }
+
+    /// Broadcasts an already signed transaction and waits for confirmation.
+    pub async fn send_solana_transaction(&self, transaction: &SolanaTransaction) -> Result<String> {
+        let provider = self.solana_provider.read().await;
+        let signature = provider.send_and_confirm_transaction(transaction)?;
+        Ok(signature.to_string())
+    }
+
+    /// Creates and initializes a durable nonce account controlled by `authority`.
+    pub async fn create_nonce_account(
+        &self,
+        payer: &dyn Signer,
+        nonce_account: &dyn Signer,
+        authority: &Pubkey,
+    ) -> Result<Pubkey> {
+        let provider = self.solana_provider.read().await;
+        let rent = provider.get_minimum_balance_for_rent_exemption(nonce::State::size())?;
+        let instructions = system_instruction::create_nonce_account(
+            &payer.pubkey(),
+            &nonce_account.pubkey(),
+            authority,
+            rent,
+        );
+
+        let recent_blockhash = provider.get_latest_blockhash()?;
+        let transaction = SolanaTransaction::new_signed_with_payer(
+            &instructions,
+            Some(&payer.pubkey()),
+            &[payer, nonce_account],
+            recent_blockhash,
+        );
+        provider.send_and_confirm_transaction(&transaction)?;
+
+        Ok(nonce_account.pubkey())
+    }
+
+    /// Returns the nonce currently stored in `nonce_account`.
+    pub async fn get_nonce(&self, nonce_account: &Pubkey) -> Result<Hash> {
+        let provider = self.solana_provider.read().await;
+        let account = nonce_utils::get_account(&provider, nonce_account)?;
+        let data = nonce_utils::data_from_account(&account)?;
+        Ok(data.blockhash())
+    }
+
+    /// Advances `nonce_account`, invalidating any transaction signed against
+    /// its current nonce.
+    pub async fn advance_nonce_account(
+        &self,
+        nonce_account: &Pubkey,
+        authority: &dyn Signer,
+        payer: &dyn Signer,
+    ) -> Result<String> {
+        let provider = self.solana_provider.read().await;
+        let instruction = system_instruction::advance_nonce_account(nonce_account, &authority.pubkey());
+
+        let recent_blockhash = provider.get_latest_blockhash()?;
+        let transaction = SolanaTransaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[payer, authority],
+            recent_blockhash,
+        );
+        let signature = provider.send_and_confirm_transaction(&transaction)?;
+        Ok(signature.to_string())
+    }
+}
+
+impl BlockchainService {
+    /// Fetches and decodes the address lookup tables at `addresses`.
+    pub async fn get_lookup_tables(&self, addresses: &[Pubkey]) -> Result<Vec<AddressLookupTableAccount>> {
+        let provider = self.solana_provider.read().await;
+        let mut tables = Vec::with_capacity(addresses.len());
+
+        for key in addresses {
+            let account = provider.get_account(key)?;
+            let table = AddressLookupTable::deserialize(&account.data)
+                .map_err(|e| anyhow!("Invalid lookup table {}: {}", key, e))?;
+            tables.push(AddressLookupTableAccount {
+                key: *key,
+                addresses: table.addresses.to_vec(),
+            });
+        }
+
+        Ok(tables)
+    }
+
+    /// Compiles `instructions` into a v0 message against a fresh blockhash,
+    /// resolving accounts through `lookup_tables` where possible.
+    pub async fn build_versioned_message(
+        &self,
+        payer: &Pubkey,
+        instructions: &[Instruction],
+        lookup_tables: &[Pubkey],
+    ) -> Result<VersionedMessage> {
+        let tables = self.get_lookup_tables(lookup_tables).await?;
+        let provider = self.solana_provider.read().await;
+        let recent_blockhash = provider.get_latest_blockhash()?;
+
+        let message = v0::Message::try_compile(payer, instructions, &tables, recent_blockhash)?;
+        Ok(VersionedMessage::V0(message))
+    }
+
+    /// Signs a versioned message. `signers` must cover every required signer
+    /// of the message, starting with the fee payer.
+    pub fn sign_versioned_transaction(
+        &self,
+        message: VersionedMessage,
+        signers: &[&dyn Signer],
+    ) -> Result<VersionedTransaction> {
+        Ok(VersionedTransaction::try_new(message, signers)?)
+    }
+
+    pub async fn send_versioned_transaction(&self, transaction: &VersionedTransaction) -> Result<String> {
+        let provider = self.solana_provider.read().await;
+        let signature = provider.send_and_confirm_transaction(transaction)?;
+        Ok(signature.to_string())
+    }
+
+    /// Creates a lookup table controlled by `authority` and returns its address.
+    pub async fn create_lookup_table(&self, authority: &dyn Signer, payer: &dyn Signer) -> Result<Pubkey> {
+        let provider = self.solana_provider.read().await;
+        let recent_slot = provider.get_slot()?;
+        let (instruction, table) = lookup_table_instruction::create_lookup_table(
+            authority.pubkey(),
+            payer.pubkey(),
+            recent_slot,
+        );
+
+        let recent_blockhash = provider.get_latest_blockhash()?;
+        let transaction = SolanaTransaction::new_signed_with_payer(
+            &[instruction],
+            Some(&payer.pubkey()),
+            &[payer, authority],
+            recent_blockhash,
+        );
+        provider.send_and_confirm_transaction(&transaction)?;
+
+        Ok(table)
+    }
+
+    /// Appends `addresses` to a lookup table, splitting them across as many
+    /// transactions as needed. Returns the transaction signatures.
+    pub async fn extend_lookup_table(
+        &self,
+        table: &Pubkey,
+        authority: &dyn Signer,
+        payer: &dyn Signer,
+        addresses: &[Pubkey],
+    ) -> Result<Vec<String>> {
+        let provider = self.solana_provider.read().await;
+        let mut signatures = Vec::new();
+
+        for chunk in addresses.chunks(LOOKUP_TABLE_EXTEND_CHUNK) {
+            let instruction = lookup_table_instruction::extend_lookup_table(
+                *table,
+                authority.pubkey(),
+                Some(payer.pubkey()),
+                chunk.to_vec(),
+            );
+
+            let recent_blockhash = provider.get_latest_blockhash()?;
+            let transaction = SolanaTransaction::new_signed_with_payer(
+                &[instruction],
+                Some(&payer.pubkey()),
+                &[payer, authority],
+                recent_blockhash,
+            );
+            signatures.push(provider.send_and_confirm_transaction(&transaction)?.to_string());
+        }
+
+        Ok(signatures)
+    }
+}
+
+/// Returns `instructions` preceded by compute unit limit and price instructions.
+/// A zero price is omitted, since it only costs transaction space.
+pub fn prepend_compute_budget(
+    instructions: Vec<Instruction>,
+    unit_limit: u32,
+    unit_price: u64,
+) -> Vec<Instruction> {
+    let mut budgeted = Vec::with_capacity(instructions.len() + 2);
+    budgeted.push(ComputeBudgetInstruction::set_compute_unit_limit(unit_limit));
+    if unit_price > 0 {
+        budgeted.push(ComputeBudgetInstruction::set_compute_unit_price(unit_price));
+    }
+    budgeted.extend(instructions);
+    budgeted
+}
+
+/// Returns `instructions` preceded by the advance-nonce instruction, which the
+/// runtime requires to be first in a durable nonce transaction.
+pub fn with_nonce_advance(
+    instructions: Vec<Instruction>,
+    nonce_account: &Pubkey,
+    authority: &Pubkey,
+) -> Vec<Instruction> {
+    let mut advanced = Vec::with_capacity(instructions.len() + 1);
+    advanced.push(system_instruction::advance_nonce_account(nonce_account, authority));
+    advanced.extend(instructions);
+    advanced
+}
+
+fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable)
+        .map(|meta| meta.pubkey)
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    accounts
+}
+
+fn compute_unit_limit_with_margin(consumed: u64) -> u32 {
+    let with_margin = consumed + consumed * COMPUTE_UNIT_MARGIN_PERCENT / 100 + COMPUTE_UNIT_HEADROOM;
+    with_margin.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+fn priority_fee_from_samples(mut samples: Vec<u64>) -> u64 {
+    if samples.is_empty() {
+        return 0;
+    }
+    samples.sort_unstable();
+    let index = (samples.len() - 1) * PRIORITY_FEE_PERCENTILE / 100;
+    samples[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{App, ChainType};
+    use solana_sdk::{compute_budget, signature::Keypair};
+    use std::sync::Arc;
+
+    async fn local_service() -> BlockchainService {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.blockchain.solana_rpc_url = "http://127.0.0.1:8899".to_string();
+        BlockchainService::new(Arc::new(App::from_config(config))).await.unwrap()
+    }
+
+    async fn airdrop(service: &BlockchainService, to: &Pubkey) {
+        let provider = service.solana_provider.read().await;
+        let signature = provider.request_airdrop(to, 1_000_000_000).unwrap();
+        while !provider.confirm_transaction(&signature).unwrap() {
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        }
+    }
+
+    fn transfer_request(from: &Pubkey, nonce_account: Option<&Pubkey>) -> TransactionRequest {
+        TransactionRequest {
+            from: from.to_string(),
+            to: Pubkey::new_unique().to_string(),
+            amount: 0.01,
+            chain_type: ChainType::Solana,
+            gas_limit: None,
+            gas_price: None,
+            data: None,
+            compute_unit_limit: None,
+            compute_unit_price: Some(1_000),
+            nonce_account: nonce_account.map(|account| account.to_string()),
+            nonce_authority: None,
+            fee_rate: None,
+        }
+    }
+
+    /// Simulates `transaction` as built and checks that its compute unit
+    /// limit covers what it consumes.
+    async fn assert_limit_covers_consumption(service: &BlockchainService, transaction: &SolanaTransaction) {
+        let message = &transaction.message;
+        let limit = message
+            .instructions
+            .iter()
+            .find(|ix| *ix.program_id(&message.account_keys) == compute_budget::id() && ix.data[0] == 2)
+            .map(|ix| u32::from_le_bytes(ix.data[1..5].try_into().unwrap()))
+            .unwrap();
+
+        let provider = service.solana_provider.read().await;
+        let result = provider
+            .simulate_transaction_with_config(
+                transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .value;
+        assert!(result.err.is_none(), "{:?}", result.err);
+        assert!(limit as u64 >= result.units_consumed.unwrap());
+    }
+
+    #[test]
+    fn test_priority_fee_percentile() {
+        assert_eq!(priority_fee_from_samples(vec![]), 0);
+        assert_eq!(priority_fee_from_samples(vec![0, 100, 10, 1000, 50]), 100);
+    }
+
+    #[test]
+    fn test_compute_budget_prepended() {
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let transfer = solana_sdk::system_instruction::transfer(&from, &to, 1);
+
+        let instructions = prepend_compute_budget(vec![transfer.clone()], 300, 5_000);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(instructions[0].program_id, compute_budget::id());
+        assert_eq!(instructions[1].program_id, compute_budget::id());
+        assert_eq!(instructions[2], transfer);
+
+        let instructions = prepend_compute_budget(vec![transfer], 300, 0);
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(compute_unit_limit_with_margin(300), 1_330);
+        assert_eq!(compute_unit_limit_with_margin(2_000_000), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn test_nonce_advance_is_first_instruction() {
+        let payer = solana_sdk::signature::Keypair::new();
+        let nonce_account = Pubkey::new_unique();
+        let transfer = solana_sdk::system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+
+        let instructions = with_nonce_advance(
+            prepend_compute_budget(vec![transfer], 300, 5_000),
+            &nonce_account,
+            &payer.pubkey(),
+        );
+        let mut message = Message::new(&instructions, Some(&payer.pubkey()));
+        message.recent_blockhash = Hash::new_unique();
+        let transaction = SolanaTransaction::new_unsigned(message);
+
+        assert!(solana_sdk::transaction::uses_durable_nonce(&transaction).is_some());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs solana-test-validator on localhost:8899"]
+    async fn test_compute_unit_limit_covers_transfer() {
+        let service = local_service().await;
+        let payer = Keypair::new();
+        airdrop(&service, &payer.pubkey()).await;
+
+        let transaction = service.build_solana_transfer(&transfer_request(&payer.pubkey(), None)).await.unwrap();
+        assert_limit_covers_consumption(&service, &transaction).await;
+    }
+
+    #[tokio::test]
+    #[ignore = "needs solana-test-validator on localhost:8899"]
+    async fn test_compute_unit_limit_covers_durable_nonce_transfer() {
+        let service = local_service().await;
+        let payer = Keypair::new();
+        airdrop(&service, &payer.pubkey()).await;
+        let nonce_account = Keypair::new();
+        service
+            .create_nonce_account(&payer, &nonce_account, &payer.pubkey())
+            .await
+            .unwrap();
+
+        let mut request = transfer_request(&payer.pubkey(), Some(&nonce_account.pubkey()));
+        let transaction = service.build_solana_transfer(&request).await.unwrap();
+        assert!(solana_sdk::transaction::uses_durable_nonce(&transaction).is_some());
+        assert_limit_covers_consumption(&service, &transaction).await;
+
+        request.nonce_authority = Some(Pubkey::new_unique().to_string());
+        assert!(service.build_solana_transfer(&request).await.is_err());
+    }
+
+    #[test]
+    fn test_versioned_message_uses_lookup_table() {
+        let payer = solana_sdk::signature::Keypair::new();
+        let to = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![to],
+        };
+        let transfer = solana_sdk::system_instruction::transfer(&payer.pubkey(), &to, 1);
+
+        let message = v0::Message::try_compile(
+            &payer.pubkey(),
+            &[transfer],
+            &[table.clone()],
+            solana_sdk::hash::Hash::default(),
+        )
+        .unwrap();
+        assert_eq!(message.address_table_lookups.len(), 1);
+        assert_eq!(message.address_table_lookups[0].account_key, table.key);
+
+        let transaction =
+            VersionedTransaction::try_new(VersionedMessage::V0(message), &[&payer]).unwrap();
+        assert!(transaction.verify_with_results().iter().all(|ok| *ok));
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The chain families the wallet supports. Serialized in lowercase; the
+/// capitalized names stored by earlier versions are still accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChainType {
+    #[serde(rename = "ethereum", alias = "Ethereum")]
+    Ethereum,
+    #[serde(rename = "solana", alias = "Solana")]
+    Solana,
+    #[serde(rename = "bitcoin", alias = "Bitcoin")]
+    Bitcoin,
+}
+
+impl fmt::Display for ChainType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ethereum => "ethereum",
+            Self::Solana => "solana",
+            Self::Bitcoin => "bitcoin",
+        })
+    }
+}
+
+impl FromStr for ChainType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ethereum" => Ok(Self::Ethereum),
+            "solana" => Ok(Self::Solana),
+            "bitcoin" => Ok(Self::Bitcoin),
+            _ => bail!("Unknown chain type {:?}", s),
+        }
+    }
+}
+
+/// A network (Solana: cluster) of a chain. Not every network exists on every
+/// chain; see `Network::supports`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Network {
+    /// Ethereum mainnet, Solana mainnet-beta or Bitcoin mainnet.
+    #[serde(alias = "mainnet-beta", alias = "bitcoin")]
+    Mainnet,
+    Sepolia,
+    Holesky,
+    Devnet,
+    Testnet,
+    Signet,
+    Regtest,
+    /// A local development node: anvil or hardhat, or solana-test-validator.
+    Localnet,
+}
+
+impl Network {
+    pub fn supports(self, chain_type: ChainType) -> bool {
+        match chain_type {
+            ChainType::Ethereum => matches!(self, Self::Mainnet | Self::Sepolia | Self::Holesky | Self::Localnet),
+            ChainType::Solana => matches!(self, Self::Mainnet | Self::Devnet | Self::Testnet | Self::Localnet),
+            ChainType::Bitcoin => matches!(self, Self::Mainnet | Self::Testnet | Self::Signet | Self::Regtest),
+        }
+    }
+
+    pub fn to_bitcoin(self) -> Result<::bitcoin::Network> {
+        Ok(match self {
+            Self::Mainnet => ::bitcoin::Network::Bitcoin,
+            Self::Testnet => ::bitcoin::Network::Testnet,
+            Self::Signet => ::bitcoin::Network::Signet,
+            Self::Regtest => ::bitcoin::Network::Regtest,
+            _ => bail!("{} is not a Bitcoin network", self),
+        })
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mainnet => "mainnet",
+            Self::Sepolia => "sepolia",
+            Self::Holesky => "holesky",
+            Self::Devnet => "devnet",
+            Self::Testnet => "testnet",
+            Self::Signet => "signet",
+            Self::Regtest => "regtest",
+            Self::Localnet => "localnet",
+        })
+    }
+}
+
+impl FromStr for Network {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(serde_json::Value::String(s.to_ascii_lowercase()))
+            .map_err(|_| anyhow!("Unknown network {:?}", s))
+    }
+}
+
+/// A chain type on a specific network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Chain {
+    pub chain_type: ChainType,
+    pub network: Network,
+}
+
+impl Chain {
+    pub fn new(chain_type: ChainType, network: Network) -> Result<Self> {
+        if !network.supports(chain_type) {
+            bail!("{} has no {} network", chain_type, network);
+        }
+        Ok(Self { chain_type, network })
+    }
+
+    /// The EIP-155 chain ID, for EVM chains.
+    pub fn chain_id(&self) -> Option<u64> {
+        match (self.chain_type, self.network) {
+            (ChainType::Ethereum, Network::Mainnet) => Some(1),
+            (ChainType::Ethereum, Network::Sepolia) => Some(11_155_111),
+            (ChainType::Ethereum, Network::Holesky) => Some(17_000),
+            (ChainType::Ethereum, Network::Localnet) => Some(31_337),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Chain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.chain_type, self.network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_serialization() {
+        assert_eq!(serde_json::to_string(&ChainType::Solana).unwrap(), "\"solana\"");
+        assert_eq!(serde_json::from_str::<ChainType>("\"Solana\"").unwrap(), ChainType::Solana);
+        assert_eq!("mainnet-beta".parse::<Network>().unwrap(), Network::Mainnet);
+        assert_eq!("bitcoin".parse::<Network>().unwrap(), Network::Mainnet);
+
+        let sepolia = Chain::new(ChainType::Ethereum, Network::Sepolia).unwrap();
+        assert_eq!(sepolia.chain_id(), Some(11_155_111));
+        assert_eq!(sepolia.to_string(), "ethereum:sepolia");
+        assert!(Chain::new(ChainType::Bitcoin, Network::Devnet).is_err());
+    }
+}
@@ -1,3 +1,8 @@
+mod chain;
+
+pub use chain::{Chain, ChainType, Network};
+
+use crate::policy::PolicyEngine;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -22,13 +27,56 @@ pub struct NetworkConfig {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct WalletConfig {
     pub storage_path: String,
+    /// SQLite or Postgres URL of the wallet database.
+    pub database_url: String,
+    /// Where the key encryption passphrase comes from: "config", "env:NAME",
+    /// "file:PATH" or "prompt".
+    pub encryption_key_source: String,
+    /// Passphrase used when `encryption_key_source` is "config".
     pub encryption_key: String,
+    /// Seconds without signing activity after which an unlocked wallet
+    /// service locks itself again.
+    pub auto_lock_timeout_secs: u64,
+    /// Upper bound on how long a single unlock lasts, in seconds.
+    pub max_unlock_secs: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BlockchainConfig {
     pub ethereum_rpc_url: String,
+    pub ethereum_network: Network,
     pub solana_rpc_url: String,
+    pub solana_network: Network,
+    pub bitcoin_rpc_url: String,
+    pub bitcoin_rpc_user: String,
+    pub bitcoin_rpc_password: String,
+    pub bitcoin_network: Network,
+    pub safe_singleton_address: String,
+    pub safe_proxy_factory_address: String,
+    pub safe_fallback_handler_address: String,
+    /// ERC-4337 bundler JSON-RPC endpoint.
+    pub bundler_url: String,
+    /// ERC-7677 paymaster service sponsoring user operation gas, if any.
+    #[serde(default)]
+    pub paymaster_url: Option<String>,
+    pub entry_point_v06_address: String,
+    pub entry_point_v07_address: String,
+    pub simple_account_factory_v06_address: String,
+    pub simple_account_factory_v07_address: String,
+    /// ENS registry; override for local chains with their own deployment.
+    pub ens_registry_address: String,
+}
+
+impl BlockchainConfig {
+    /// The configured network of `chain_type`.
+    pub fn chain(&self, chain_type: ChainType) -> Result<Chain> {
+        let network = match chain_type {
+            ChainType::Ethereum => self.ethereum_network,
+            ChainType::Solana => self.solana_network,
+            ChainType::Bitcoin => self.bitcoin_network,
+        };
+        Chain::new(chain_type, network)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -40,6 +88,7 @@ pub struct DeFiConfig {
 pub struct App {
     config: Arc<RwLock<AppConfig>>,
     state: Arc<RwLock<AppState>>,
+    policy_engine: Arc<PolicyEngine>,
 }
 
 #[derive(Default)]
@@ -53,10 +102,22 @@ impl App {
     pub async fn new() -> Result<Self> {
         let config = Self::load_config()?;
         
-        Ok(Self {
+        Ok(Self::from_config(config))
+    }
+
+    /// Builds an app from an already loaded configuration instead of the
+    /// `config/` directory.
+    pub fn from_config(config: AppConfig) -> Self {
+        Self {
             config: Arc::new(RwLock::new(config)),
             state: Arc::new(RwLock::new(AppState::default())),
-        })
+            policy_engine: Arc::new(PolicyEngine::default()),
+        }
+    }
+
+    /// Spending policies consulted before any wallet key signs.
+    pub fn policy_engine(&self) -> &PolicyEngine {
+        &self.policy_engine
     }
 
     fn load_config() -> Result<AppConfig, ConfigError> {
@@ -72,6 +133,16 @@ impl App {
         self.config.read().await.clone()
     }
 
+    /// Changes the configuration in place. Services read most settings when
+    /// they are built, so changes apply to services built afterwards.
+    pub async fn update_config<F>(&self, f: F)
+    where
+        F: FnOnce(&mut AppConfig),
+    {
+        let mut config = self.config.write().await;
+        f(&mut config);
+    }
+
     pub async fn update_state<F>(&self, f: F)
     where
         F: FnOnce(&mut AppState),
@@ -1,5 +1,8 @@
+pub use crate::core::ChainType;
+
 use crate::core::App;
-use anyhow::Result;
+use crate::policy::{Asset, Spend};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use ethers::{
     providers::{Http, Provider},
@@ -24,6 +27,8 @@ pub struct TokenInfo {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SwapRequest {
+    /// Wallet that executes the swap.
+    pub from: String,
     pub from_token: TokenInfo,
     pub to_token: TokenInfo,
     pub amount: f64,
@@ -39,12 +44,6 @@ pub enum DeFiProtocol {
     Curve,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ChainType {
-    Ethereum,
-    Solana,
-}
-
 impl DeFiService {
     pub async fn new(app: Arc<App>) -> Result<Self> {
         let config = app.get_config().await;
@@ -82,6 +81,7 @@ impl DeFiService {
                 // Implement Solana token balance check
                 Ok(0.0)
             }
+            ChainType::Bitcoin => Err(anyhow!("Bitcoin has no token balances")),
         }
     }
 
@@ -139,6 +139,25 @@ impl DeFiService {
     }
 
     pub async fn execute_swap(&self, request: SwapRequest) -> Result<String> {
+        let contracts = [&request.from_token, &request.to_token]
+            .into_iter()
+            .filter(|token| Asset::from_token_address(&token.address) != Asset::Native)
+            .map(|token| token.address.clone())
+            .collect();
+        self.app
+            .policy_engine()
+            .authorize(&Spend {
+                wallet: request.from.clone(),
+                chain_type: request.from_token.chain_type,
+                recipient: None,
+                asset: Asset::from_token_address(&request.from_token.address),
+                amount: request.amount,
+                fee: None,
+                contracts,
+                approved: false,
+            })
+            .await?;
+
         // Implement swap execution logic
         Ok("transaction_hash".to_string())
     }
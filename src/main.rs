@@ -3,6 +3,7 @@ mod network;
 mod wallet;
 mod blockchain;
 mod defi;
+mod policy;
 mod utils;
 
 use anyhow::Result;
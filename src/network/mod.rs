@@ -1,4 +1,4 @@
-use crate::core::App;
+use crate::core::{App, ChainType};
 use anyhow::Result;
 use async_trait::async_trait;
 use futures::StreamExt;
@@ -25,7 +25,7 @@ pub enum NetworkMessage {
         from: String,
         to: String,
         amount: f64,
-        chain_type: String,
+        chain_type: ChainType,
     },
     PeerDiscovery {
         peers: Vec<String>,
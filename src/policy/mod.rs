@@ -0,0 +1,614 @@
+use crate::core::ChainType;
+use chrono::{DateTime, Duration, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{OnceCell, RwLock};
+
+/// Placeholder address DEX aggregators and the swap examples use for the
+/// native asset.
+const NATIVE_TOKEN_ADDRESS: &str = "0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+
+/// Guardrails for one wallet. Every limit is optional; the default policy
+/// allows everything.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpendingPolicy {
+    /// Native amount (transfers plus fees) the wallet may spend in any 24 hours.
+    pub daily_limit: Option<f64>,
+    /// Native amount the wallet may spend in any 7 days.
+    pub weekly_limit: Option<f64>,
+    /// Limits per token contract address.
+    pub token_limits: HashMap<String, TokenLimit>,
+    /// When set, transfers may only go to these addresses.
+    pub allowed_recipients: Option<Vec<String>>,
+    pub denied_recipients: Vec<String>,
+    /// Maximum fee of a single transaction, in native units.
+    pub max_fee: Option<f64>,
+    /// When set, contract calls and swaps may only touch these contracts.
+    pub allowed_contracts: Option<Vec<String>>,
+    /// When set, the wallet may only spend during this UTC time of day.
+    pub allowed_hours: Option<TimeWindow>,
+    /// When set, large transfers must be proposed and approved first.
+    pub approval: Option<ApprovalRule>,
+}
+
+/// Native transfers above `threshold` need `quorum` of `approvers` (Ethereum
+/// addresses) to approve a proposal within `expiry_secs` before they are sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRule {
+    pub threshold: f64,
+    pub approvers: Vec<String>,
+    pub quorum: usize,
+    pub expiry_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenLimit {
+    pub daily: Option<f64>,
+    pub weekly: Option<f64>,
+}
+
+/// Hours of the day in UTC, `start` inclusive and `end` exclusive. A window
+/// with `start > end` wraps past midnight.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TimeWindow {
+    pub start_hour: u32,
+    pub end_hour: u32,
+}
+
+impl TimeWindow {
+    fn contains(&self, hour: u32) -> bool {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Asset {
+    Native,
+    /// A token, by contract address.
+    Token(String),
+}
+
+impl Asset {
+    /// Classifies a token address, mapping the native placeholder to `Native`.
+    pub fn from_token_address(address: &str) -> Self {
+        if address.eq_ignore_ascii_case(NATIVE_TOKEN_ADDRESS) {
+            Self::Native
+        } else {
+            Self::Token(address.to_lowercase())
+        }
+    }
+}
+
+/// Something a wallet is about to sign.
+#[derive(Debug, Clone)]
+pub struct Spend {
+    pub wallet: String,
+    pub chain_type: ChainType,
+    /// `None` when value stays with the wallet, as in a swap.
+    pub recipient: Option<String>,
+    pub asset: Asset,
+    pub amount: f64,
+    /// Fee in native units, when known.
+    pub fee: Option<f64>,
+    /// Contracts the transaction calls.
+    pub contracts: Vec<String>,
+    /// Whether the spend is an approved proposal, exempting it from the
+    /// wallet's `ApprovalRule`.
+    pub approved: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum PolicyViolation {
+    #[error("recipient {0} is on the wallet's deny list")]
+    RecipientDenied(String),
+    #[error("recipient {0} is not on the wallet's allow list")]
+    RecipientNotAllowed(String),
+    #[error("contract {0} is not on the wallet's contract allowlist")]
+    ContractNotAllowed(String),
+    #[error("fee of {fee} exceeds the maximum of {max} per transaction")]
+    FeeTooHigh { fee: f64, max: f64 },
+    #[error("transfers above {threshold} need approval; propose the transaction instead")]
+    ApprovalRequired { threshold: f64 },
+    #[error("spending is only allowed between {start}:00 and {end}:00 UTC")]
+    OutsideAllowedHours { start: u32, end: u32 },
+    #[error("{period} limit of {limit} {asset} exceeded: {spent} already spent, {requested} requested")]
+    LimitExceeded {
+        period: &'static str,
+        asset: String,
+        limit: f64,
+        spent: f64,
+        requested: f64,
+    },
+    #[error("spend history could not be stored: {0}")]
+    HistoryUnavailable(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SpendRecord {
+    wallet: String,
+    asset: Asset,
+    amount: f64,
+    at: DateTime<Utc>,
+    reservation: u64,
+}
+
+/// A spend counted towards its wallet's limits while the transaction is
+/// signed and broadcast. Pass it to `PolicyEngine::commit` once the
+/// transaction is sent, or to `PolicyEngine::release` if it fails.
+#[must_use = "a reservation must be committed or released"]
+#[derive(Debug)]
+pub struct SpendReservation {
+    id: u64,
+}
+
+/// Policies and spend records stored as JSON documents in the wallet
+/// database, so limits and the spending counted against them survive
+/// restarts.
+struct PolicyStore {
+    pool: AnyPool,
+}
+
+impl PolicyStore {
+    async fn new(pool: AnyPool) -> anyhow::Result<Self> {
+        sqlx::query("CREATE TABLE IF NOT EXISTS policies (wallet TEXT PRIMARY KEY, data TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+        sqlx::query("CREATE TABLE IF NOT EXISTS spends (reservation BIGINT NOT NULL, data TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    async fn load_policies(&self) -> anyhow::Result<Vec<(String, SpendingPolicy)>> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT wallet, data FROM policies")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|(wallet, data)| Ok((wallet, serde_json::from_str(&data)?)))
+            .collect()
+    }
+
+    async fn save_policy(&self, wallet: &str, policy: &SpendingPolicy) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM policies WHERE wallet = $1")
+            .bind(wallet)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO policies (wallet, data) VALUES ($1, $2)")
+            .bind(wallet)
+            .bind(serde_json::to_string(policy)?)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_policy(&self, wallet: &str) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM policies WHERE wallet = $1")
+            .bind(wallet)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_spends(&self) -> anyhow::Result<Vec<SpendRecord>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM spends")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|(data,)| Ok(serde_json::from_str(&data)?))
+            .collect()
+    }
+
+    async fn insert_spends(&self, records: &[SpendRecord]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for record in records {
+            sqlx::query("INSERT INTO spends (reservation, data) VALUES ($1, $2)")
+                .bind(record.reservation as i64)
+                .bind(serde_json::to_string(record)?)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_spends(&self, reservation: u64) -> anyhow::Result<()> {
+        sqlx::query("DELETE FROM spends WHERE reservation = $1")
+            .bind(reservation as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Holds per-wallet spending policies and the spend history they are checked
+/// against. Shared through `App`, so transfers and swaps count towards the
+/// same limits. Until `load` attaches the wallet database, policies and
+/// history are kept in memory only.
+#[derive(Default)]
+pub struct PolicyEngine {
+    policies: RwLock<HashMap<String, SpendingPolicy>>,
+    history: RwLock<Vec<SpendRecord>>,
+    next_reservation: AtomicU64,
+    store: OnceCell<PolicyStore>,
+}
+
+impl PolicyEngine {
+    /// Loads the policies and the last week's spends stored in the wallet
+    /// database behind `pool`, and stores every later change there. Only
+    /// the first call has an effect.
+    pub async fn load(&self, pool: AnyPool) -> anyhow::Result<()> {
+        self.store
+            .get_or_try_init(|| async move {
+                let store = PolicyStore::new(pool).await?;
+
+                let mut policies = self.policies.write().await;
+                for (wallet, policy) in policies.iter() {
+                    store.save_policy(wallet, policy).await?;
+                }
+                policies.extend(store.load_policies().await?);
+
+                let now = Utc::now();
+                let mut history = self.history.write().await;
+                store.insert_spends(&history).await?;
+                for record in store.load_spends().await? {
+                    if now - record.at >= Duration::days(7) {
+                        store.delete_spends(record.reservation).await?;
+                    } else if !history.iter().any(|known| known.reservation == record.reservation) {
+                        self.next_reservation.fetch_max(record.reservation + 1, Ordering::Relaxed);
+                        history.push(record);
+                    }
+                }
+                Ok::<_, anyhow::Error>(store)
+            })
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_policy(
+        &self,
+        wallet: &str,
+        chain_type: ChainType,
+        policy: SpendingPolicy,
+    ) -> anyhow::Result<()> {
+        let wallet = wallet_key(wallet, chain_type);
+        let mut policies = self.policies.write().await;
+        if let Some(store) = self.store.get() {
+            store.save_policy(&wallet, &policy).await?;
+        }
+        policies.insert(wallet, policy);
+        Ok(())
+    }
+
+    pub async fn remove_policy(&self, wallet: &str, chain_type: ChainType) -> anyhow::Result<()> {
+        let wallet = wallet_key(wallet, chain_type);
+        let mut policies = self.policies.write().await;
+        if let Some(store) = self.store.get() {
+            store.delete_policy(&wallet).await?;
+        }
+        policies.remove(&wallet);
+        Ok(())
+    }
+
+    pub async fn get_policy(&self, wallet: &str, chain_type: ChainType) -> Option<SpendingPolicy> {
+        self.policies.read().await.get(&wallet_key(wallet, chain_type)).cloned()
+    }
+
+    /// Checks `spend` against the wallet's policy and, if it passes, records
+    /// it towards the wallet's limits. Wallets without a policy pass. Use
+    /// `reserve` instead when the spend may still fail after the check.
+    pub async fn authorize(&self, spend: &Spend) -> Result<(), PolicyViolation> {
+        let reservation = self.reserve(spend).await?;
+        self.commit(reservation);
+        Ok(())
+    }
+
+    /// Checks `spend` against the wallet's policy and, if it passes, holds
+    /// its amount against the wallet's limits until it is committed or
+    /// released.
+    pub async fn reserve(&self, spend: &Spend) -> Result<SpendReservation, PolicyViolation> {
+        self.reserve_at(spend, Utc::now()).await
+    }
+
+    /// Keeps the reserved spend in the wallet's history; it is already
+    /// counted there, so this only consumes the reservation.
+    pub fn commit(&self, _reservation: SpendReservation) {}
+
+    /// Drops the reserved spend, as its transaction was never sent. If the
+    /// stored record can't be deleted it keeps counting after a restart,
+    /// which errs on the side of the limit.
+    pub async fn release(&self, reservation: SpendReservation) {
+        let mut history = self.history.write().await;
+        if let Some(store) = self.store.get() {
+            if let Err(e) = store.delete_spends(reservation.id).await {
+                log::warn!("Failed to delete released spend {}: {}", reservation.id, e);
+            }
+        }
+        history.retain(|record| record.reservation != reservation.id);
+    }
+
+    async fn reserve_at(&self, spend: &Spend, now: DateTime<Utc>) -> Result<SpendReservation, PolicyViolation> {
+        let reservation = SpendReservation {
+            id: self.next_reservation.fetch_add(1, Ordering::Relaxed),
+        };
+        let wallet = wallet_key(&spend.wallet, spend.chain_type);
+        let policies = self.policies.read().await;
+        let Some(policy) = policies.get(&wallet) else {
+            return Ok(reservation);
+        };
+
+        // Hold the history lock from check to record so concurrent spends
+        // can't both fit under a limit only one of them should
+        let mut history = self.history.write().await;
+        history.retain(|record| now - record.at < Duration::days(7));
+
+        check_static_rules(policy, spend, now)?;
+
+        let native = spend.amount_of(&Asset::Native) + spend.fee.unwrap_or(0.0);
+        check_limits(&history, &wallet, &Asset::Native, native, policy.daily_limit, policy.weekly_limit, now)?;
+        if let Asset::Token(token) = &spend.asset {
+            let limit = policy
+                .token_limits
+                .iter()
+                .find(|(address, _)| address.eq_ignore_ascii_case(token))
+                .map(|(_, limit)| limit);
+            if let Some(limit) = limit {
+                check_limits(&history, &wallet, &spend.asset, spend.amount, limit.daily, limit.weekly, now)?;
+            }
+        }
+
+        let mut records = Vec::new();
+        if native > 0.0 {
+            records.push(SpendRecord {
+                wallet: wallet.clone(),
+                asset: Asset::Native,
+                amount: native,
+                at: now,
+                reservation: reservation.id,
+            });
+        }
+        if let Asset::Token(_) = spend.asset {
+            records.push(SpendRecord {
+                wallet,
+                asset: spend.asset.clone(),
+                amount: spend.amount,
+                at: now,
+                reservation: reservation.id,
+            });
+        }
+
+        // A spend that isn't stored would be forgotten on restart, so it fails
+        if let Some(store) = self.store.get() {
+            store
+                .insert_spends(&records)
+                .await
+                .map_err(|e| PolicyViolation::HistoryUnavailable(e.to_string()))?;
+        }
+        history.extend(records);
+        Ok(reservation)
+    }
+}
+
+impl Spend {
+    fn amount_of(&self, asset: &Asset) -> f64 {
+        if self.asset == *asset {
+            self.amount
+        } else {
+            0.0
+        }
+    }
+}
+
+fn check_static_rules(policy: &SpendingPolicy, spend: &Spend, now: DateTime<Utc>) -> Result<(), PolicyViolation> {
+    if let Some(window) = policy.allowed_hours {
+        if !window.contains(now.hour()) {
+            return Err(PolicyViolation::OutsideAllowedHours {
+                start: window.start_hour,
+                end: window.end_hour,
+            });
+        }
+    }
+
+    if let Some(recipient) = &spend.recipient {
+        let key = wallet_key(recipient, spend.chain_type);
+        if policy.denied_recipients.iter().any(|denied| wallet_key(denied, spend.chain_type) == key) {
+            return Err(PolicyViolation::RecipientDenied(recipient.clone()));
+        }
+        if let Some(allowed) = &policy.allowed_recipients {
+            if !allowed.iter().any(|allowed| wallet_key(allowed, spend.chain_type) == key) {
+                return Err(PolicyViolation::RecipientNotAllowed(recipient.clone()));
+            }
+        }
+    }
+
+    for contract in &spend.contracts {
+        let key = wallet_key(contract, spend.chain_type);
+        if policy.denied_recipients.iter().any(|denied| wallet_key(denied, spend.chain_type) == key) {
+            return Err(PolicyViolation::RecipientDenied(contract.clone()));
+        }
+    }
+
+    if let Some(allowed) = &policy.allowed_contracts {
+        for contract in &spend.contracts {
+            let key = wallet_key(contract, spend.chain_type);
+            if !allowed.iter().any(|allowed| wallet_key(allowed, spend.chain_type) == key) {
+                return Err(PolicyViolation::ContractNotAllowed(contract.clone()));
+            }
+        }
+    }
+
+    if let Some(rule) = &policy.approval {
+        if !spend.approved && spend.amount_of(&Asset::Native) > rule.threshold {
+            return Err(PolicyViolation::ApprovalRequired { threshold: rule.threshold });
+        }
+    }
+
+    if let (Some(fee), Some(max)) = (spend.fee, policy.max_fee) {
+        if fee > max {
+            return Err(PolicyViolation::FeeTooHigh { fee, max });
+        }
+    }
+
+    Ok(())
+}
+
+fn check_limits(
+    history: &[SpendRecord],
+    wallet: &str,
+    asset: &Asset,
+    requested: f64,
+    daily: Option<f64>,
+    weekly: Option<f64>,
+    now: DateTime<Utc>,
+) -> Result<(), PolicyViolation> {
+    for (period, limit, window) in [("daily", daily, Duration::days(1)), ("weekly", weekly, Duration::days(7))] {
+        let Some(limit) = limit else { continue };
+        let spent: f64 = history
+            .iter()
+            .filter(|record| record.wallet == wallet && record.asset == *asset && now - record.at < window)
+            .map(|record| record.amount)
+            .sum();
+
+        if spent + requested > limit {
+            return Err(PolicyViolation::LimitExceeded {
+                period,
+                asset: match asset {
+                    Asset::Native => "native".to_string(),
+                    Asset::Token(token) => token.clone(),
+                },
+                limit,
+                spent,
+                requested,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Ethereum addresses are compared case-insensitively; Solana and Bitcoin
+/// addresses are case-sensitive (base58) or canonically lowercase (bech32).
+fn wallet_key(address: &str, chain_type: ChainType) -> String {
+    match chain_type {
+        ChainType::Ethereum => address.trim().to_lowercase(),
+        ChainType::Solana | ChainType::Bitcoin => address.trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{App, AppConfig};
+    use crate::wallet::WalletService;
+    use chrono::TimeZone;
+    use std::sync::Arc;
+
+    fn transfer(amount: f64) -> Spend {
+        Spend {
+            wallet: "0xAbC0000000000000000000000000000000000001".to_string(),
+            chain_type: ChainType::Ethereum,
+            recipient: Some("0xdef0000000000000000000000000000000000002".to_string()),
+            asset: Asset::Native,
+            amount,
+            fee: Some(0.001),
+            contracts: Vec::new(),
+            approved: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_policy_enforcement() {
+        let engine = PolicyEngine::default();
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        engine
+            .set_policy(
+                "0xabc0000000000000000000000000000000000001",
+                ChainType::Ethereum,
+                SpendingPolicy {
+                    daily_limit: Some(1.0),
+                    denied_recipients: vec!["0xDEF0000000000000000000000000000000000002".to_string()],
+                    allowed_hours: Some(TimeWindow { start_hour: 9, end_hour: 17 }),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            engine.reserve_at(&transfer(0.1), now).await,
+            Err(PolicyViolation::RecipientDenied(_))
+        ));
+
+        let mut spend = transfer(0.6);
+        spend.recipient = Some("0x1230000000000000000000000000000000000003".to_string());
+        engine.commit(engine.reserve_at(&spend, now).await.unwrap());
+        assert!(matches!(
+            engine.reserve_at(&spend, now).await,
+            Err(PolicyViolation::LimitExceeded { period: "daily", .. })
+        ));
+        assert!(matches!(
+            engine.reserve_at(&spend, now + Duration::hours(8)).await,
+            Err(PolicyViolation::OutsideAllowedHours { .. })
+        ));
+
+        // The first spend leaves the 24 hour window the next day
+        engine.commit(engine.reserve_at(&spend, now + Duration::hours(25)).await.unwrap());
+
+        // A released spend, whose transaction failed, no longer counts
+        let later = now + Duration::hours(26);
+        let mut small = spend.clone();
+        small.amount = 0.3;
+        engine.release(engine.reserve_at(&small, later).await.unwrap()).await;
+        engine.commit(engine.reserve_at(&small, later).await.unwrap());
+        assert!(engine.reserve_at(&small, later).await.is_err());
+    }
+
+    /// An app whose policy engine was loaded by its wallet service, as at startup.
+    async fn start(config: &AppConfig) -> Arc<App> {
+        let app = Arc::new(App::from_config(config.clone()));
+        WalletService::new(app.clone()).await.unwrap();
+        app
+    }
+
+    #[tokio::test]
+    async fn test_policies_and_spends_survive_restart() {
+        let path = std::env::temp_dir().join(format!("policies-{}.db", uuid::Uuid::new_v4()));
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = format!("sqlite://{}?mode=rwc", path.display());
+        config.wallet.encryption_key_source = "config".to_string();
+
+        let wallet = "0xabc0000000000000000000000000000000000001";
+        let policy = SpendingPolicy {
+            daily_limit: Some(1.0),
+            ..Default::default()
+        };
+        let app = start(&config).await;
+        let engine = app.policy_engine();
+        engine.set_policy(wallet, ChainType::Ethereum, policy).await.unwrap();
+        engine.commit(engine.reserve(&transfer(0.6)).await.unwrap());
+        engine.release(engine.reserve(&transfer(0.2)).await.unwrap()).await;
+        drop(app);
+
+        // After a restart the limit still applies, counting the committed
+        // spend but not the released one
+        let app = start(&config).await;
+        let engine = app.policy_engine();
+        assert_eq!(engine.get_policy(wallet, ChainType::Ethereum).await.unwrap().daily_limit, Some(1.0));
+        assert!(matches!(
+            engine.reserve(&transfer(0.6)).await,
+            Err(PolicyViolation::LimitExceeded { period: "daily", .. })
+        ));
+        engine.commit(engine.reserve(&transfer(0.3)).await.unwrap());
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,274 @@
+use super::{normalize_address, ChainType};
+use crate::blockchain::ens::{self, EnsResolver};
+use crate::core::Network;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::any::AnyPool;
+
+/// Characters compared at each end of an address when looking for
+/// lookalikes. Address-poisoning attacks generate vanity addresses that match
+/// what wallets show of a real one, typically its first and last few characters.
+const LOOKALIKE_PREFIX_LEN: usize = 4;
+const LOOKALIKE_SUFFIX_LEN: usize = 4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Contact {
+    pub id: String,
+    pub name: String,
+    pub addresses: Vec<ContactAddress>,
+    pub notes: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactAddress {
+    pub chain_type: ChainType,
+    /// May be given as an ENS name, which is resolved when the contact is
+    /// saved.
+    pub address: String,
+    /// The ENS name `address` was resolved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ens_name: Option<String>,
+}
+
+/// Reasons to double-check a recipient before sending to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecipientWarning {
+    /// The recipient is neither a contact nor one of our own wallets.
+    FirstTime,
+    /// The recipient starts and ends like `address`, a known address it is
+    /// not. `contact` is `None` when `address` is one of our own wallets.
+    Lookalike { address: String, contact: Option<String> },
+}
+
+/// Named contacts with validated per-chain addresses, stored alongside the
+/// wallets in the wallet database.
+#[derive(Clone)]
+pub struct AddressBook {
+    pool: AnyPool,
+    bitcoin_network: Network,
+    ens: EnsResolver,
+}
+
+impl AddressBook {
+    pub(crate) async fn new(pool: AnyPool, bitcoin_network: Network, ens: EnsResolver) -> Result<Self> {
+        sqlx::query("CREATE TABLE IF NOT EXISTS contacts (id TEXT PRIMARY KEY, name TEXT NOT NULL UNIQUE, data TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool, bitcoin_network, ens })
+    }
+
+    pub async fn add_contact(
+        &self,
+        name: &str,
+        addresses: Vec<ContactAddress>,
+        notes: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<Contact> {
+        let contact = self.validate(Contact {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            addresses,
+            notes,
+            tags,
+            created_at: Utc::now(),
+        })
+        .await?;
+
+        if self.get_contact(&contact.name).await?.is_some() {
+            return Err(anyhow!("Contact {} already exists", contact.name));
+        }
+
+        sqlx::query("INSERT INTO contacts (id, name, data) VALUES ($1, $2, $3)")
+            .bind(&contact.id)
+            .bind(&contact.name)
+            .bind(serde_json::to_string(&contact)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(contact)
+    }
+
+    /// Replaces the stored contact with the same id.
+    pub async fn update_contact(&self, contact: Contact) -> Result<Contact> {
+        let contact = self.validate(contact).await?;
+        let updated = sqlx::query("UPDATE contacts SET name = $1, data = $2 WHERE id = $3")
+            .bind(&contact.name)
+            .bind(serde_json::to_string(&contact)?)
+            .bind(&contact.id)
+            .execute(&self.pool)
+            .await?;
+
+        if updated.rows_affected() == 0 {
+            return Err(anyhow!("No contact with id {}", contact.id));
+        }
+        Ok(contact)
+    }
+
+    pub async fn remove_contact(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM contacts WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_contact(&self, name: &str) -> Result<Option<Contact>> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT data FROM contacts WHERE name = $1")
+            .bind(name.trim())
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|(data,)| Ok(serde_json::from_str(&data)?)).transpose()
+    }
+
+    /// Lists contacts sorted by name, optionally only those tagged `tag`.
+    pub async fn list_contacts(&self, tag: Option<&str>) -> Result<Vec<Contact>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM contacts ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut contacts = Vec::with_capacity(rows.len());
+        for (data,) in rows {
+            let contact: Contact = serde_json::from_str(&data)?;
+            if tag.is_none_or(|tag| contact.tags.iter().any(|t| t == tag)) {
+                contacts.push(contact);
+            }
+        }
+        Ok(contacts)
+    }
+
+    pub async fn find_by_address(&self, address: &str, chain_type: ChainType) -> Result<Option<Contact>> {
+        let address = self.resolve(address, chain_type).await?;
+        Ok(self.list_contacts(None).await?.into_iter().find(|contact| {
+            contact
+                .addresses
+                .iter()
+                .any(|a| a.chain_type == chain_type && a.address == address)
+        }))
+    }
+
+    /// Checks `address` (or ENS name) against the contacts and
+    /// `own_addresses` (our wallets on `chain_type`). An invalid address is
+    /// an error, not a warning.
+    pub async fn check_recipient(
+        &self,
+        address: &str,
+        chain_type: ChainType,
+        own_addresses: &[String],
+    ) -> Result<Vec<RecipientWarning>> {
+        let address = self.resolve(address, chain_type).await?;
+
+        let mut known: Vec<(String, Option<String>)> = own_addresses.iter().map(|a| (a.clone(), None)).collect();
+        for contact in self.list_contacts(None).await? {
+            for entry in contact.addresses.into_iter().filter(|a| a.chain_type == chain_type) {
+                known.push((entry.address, Some(contact.name.clone())));
+            }
+        }
+
+        if known.iter().any(|(known, _)| *known == address) {
+            return Ok(Vec::new());
+        }
+
+        let mut warnings = vec![RecipientWarning::FirstTime];
+        for (known, contact) in known {
+            if is_lookalike(&address, &known, chain_type) {
+                warnings.push(RecipientWarning::Lookalike { address: known, contact });
+            }
+        }
+        Ok(warnings)
+    }
+
+    /// Normalizes `address`, resolving it first if it is an ENS name.
+    async fn resolve(&self, address: &str, chain_type: ChainType) -> Result<String> {
+        let address = match chain_type {
+            ChainType::Ethereum => self.ens.resolve_recipient(address).await?,
+            _ => address.to_string(),
+        };
+        normalize_address(&address, &chain_type, self.bitcoin_network)
+    }
+
+    /// Normalizes the contact's name, tags and addresses, resolving ENS
+    /// names and rejecting invalid addresses.
+    async fn validate(&self, mut contact: Contact) -> Result<Contact> {
+        contact.name = contact.name.trim().to_string();
+        if contact.name.is_empty() {
+            return Err(anyhow!("Contact name must not be empty"));
+        }
+
+        for entry in &mut contact.addresses {
+            if entry.chain_type == ChainType::Ethereum && ens::is_ens_name(&entry.address) {
+                entry.ens_name = Some(ens::normalize(&entry.address)?);
+            }
+            entry.address = self
+                .resolve(&entry.address, entry.chain_type)
+                .await
+                .map_err(|e| anyhow!("Invalid {:?} address {}: {}", entry.chain_type, entry.address, e))?;
+        }
+        let mut seen = Vec::new();
+        contact.addresses.retain(|entry| {
+            let key = (entry.chain_type, entry.address.clone());
+            let duplicate = seen.contains(&key);
+            seen.push(key);
+            !duplicate
+        });
+
+        contact.tags = contact
+            .tags
+            .iter()
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        contact.tags.sort();
+        contact.tags.dedup();
+
+        Ok(contact)
+    }
+}
+
+/// Whether two different addresses share the characters a user is likely to
+/// eyeball. Fixed encoding prefixes (`0x`, `bc1q`, ...) are skipped first, as
+/// every address has them.
+fn is_lookalike(a: &str, b: &str, chain_type: ChainType) -> bool {
+    let (a, b) = (significant_part(a, chain_type), significant_part(b, chain_type));
+    if a == b || a.len() < LOOKALIKE_PREFIX_LEN + LOOKALIKE_SUFFIX_LEN || b.len() < LOOKALIKE_PREFIX_LEN + LOOKALIKE_SUFFIX_LEN {
+        return false;
+    }
+
+    a[..LOOKALIKE_PREFIX_LEN].eq_ignore_ascii_case(&b[..LOOKALIKE_PREFIX_LEN])
+        && a[a.len() - LOOKALIKE_SUFFIX_LEN..].eq_ignore_ascii_case(&b[b.len() - LOOKALIKE_SUFFIX_LEN..])
+}
+
+fn significant_part(address: &str, chain_type: ChainType) -> &str {
+    match chain_type {
+        ChainType::Ethereum => address.trim_start_matches("0x"),
+        ChainType::Solana => address,
+        // Bech32: skip the human-readable part, separator and witness version
+        ChainType::Bitcoin => {
+            let lowercase = address.to_ascii_lowercase();
+            if ["bc1", "tb1", "bcrt1"].iter().any(|hrp| lowercase.starts_with(hrp)) {
+                address.rfind('1').and_then(|separator| address.get(separator + 2..)).unwrap_or(address)
+            } else {
+                address
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookalike_addresses() {
+        let real = "0x52908400098527886e0f7030069857d2e4169ee7";
+        let poisoned = "0x5290a1b2c3d4e5f60718293a4b5c6d7e8f909ee7";
+        let unrelated = "0x8617e340b3d01fa5f11f306f4090fd50e238070d";
+
+        assert!(is_lookalike(poisoned, real, ChainType::Ethereum));
+        assert!(!is_lookalike(unrelated, real, ChainType::Ethereum));
+        assert!(!is_lookalike(real, real, ChainType::Ethereum));
+    }
+}
@@ -0,0 +1,82 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{anyhow, bail, Result};
+use rand::RngCore;
+
+const FORMAT_VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// scrypt cost for at-rest key encryption: N = 2^15, r = 8, p = 1.
+const SCRYPT_LOG_N: u8 = 15;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+
+/// Encrypts `plaintext` under `passphrase` with AES-256-GCM, using a key
+/// derived by scrypt from a fresh random salt.
+///
+/// Layout: version (1) | salt (16) | nonce (12) | ciphertext and tag.
+pub fn encrypt(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+
+    let cipher = Aes256Gcm::new_from_slice(&derive_key(passphrase, &salt)?)
+        .map_err(|_| anyhow!("Invalid encryption key length"))?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+
+    let mut encrypted = Vec::with_capacity(1 + SALT_LEN + NONCE_LEN + ciphertext.len());
+    encrypted.push(FORMAT_VERSION);
+    encrypted.extend_from_slice(&salt);
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend_from_slice(&ciphertext);
+    Ok(encrypted)
+}
+
+/// Decrypts data produced by `encrypt`. Fails on a wrong passphrase or any
+/// tampering, since the GCM tag authenticates the whole ciphertext.
+pub fn decrypt(encrypted: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if encrypted.len() < 1 + SALT_LEN + NONCE_LEN {
+        bail!("Encrypted key is truncated");
+    }
+    if encrypted[0] != FORMAT_VERSION {
+        bail!("Unsupported encrypted key version {}", encrypted[0]);
+    }
+
+    let (salt, rest) = encrypted[1..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let cipher = Aes256Gcm::new_from_slice(&derive_key(passphrase, salt)?)
+        .map_err(|_| anyhow!("Invalid encryption key length"))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted key"))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; KEY_LEN]> {
+    let params = scrypt::Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, KEY_LEN)
+        .map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+    let mut key = [0u8; KEY_LEN];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|e| anyhow!("Scrypt failed: {}", e))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let encrypted = encrypt(b"secret key", "passphrase").unwrap();
+        assert_eq!(decrypt(&encrypted, "passphrase").unwrap(), b"secret key");
+        assert!(decrypt(&encrypted, "wrong").is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{anyhow, bail, Result};
+use aes::cipher::{KeyIvInit, StreamCipher};
+use ethers::core::k256::ecdsa::SigningKey;
+use hmac::Hmac;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+use subtle::ConstantTimeEq;
+use zeroize::Zeroizing;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+const CIPHER: &str = "aes-128-ctr";
+const DERIVED_KEY_LEN: usize = 32;
+
+/// geth's "standard" scrypt cost: N = 2^18, r = 8, p = 1.
+const SCRYPT_LOG_N: u8 = 18;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 1;
+const PBKDF2_ROUNDS: u32 = 262_144;
+
+/// Upper bounds on the cost a keystore file may ask for, so importing a
+/// hostile file cannot exhaust memory or stall the process. Scrypt needs
+/// 128 * n * r bytes; the cap admits geth's standard parameters.
+const MAX_SCRYPT_MEMORY: u64 = 256 * 1024 * 1024;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_PBKDF2_ROUNDS: u32 = 10_000_000;
+
+/// Key derivation function used when exporting a keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Scrypt,
+    Pbkdf2,
+}
+
+/// A Web3 Secret Storage Definition (version 3) keystore file.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeystoreV3 {
+    pub version: u8,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    // Older geth releases capitalized this field
+    #[serde(alias = "Crypto")]
+    pub crypto: CryptoJson,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CryptoJson {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    #[serde(with = "hex")]
+    pub ciphertext: Vec<u8>,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    #[serde(with = "hex")]
+    pub mac: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CipherParams {
+    #[serde(with = "hex")]
+    pub iv: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt {
+        dklen: usize,
+        n: u32,
+        r: u32,
+        p: u32,
+        #[serde(with = "hex")]
+        salt: Vec<u8>,
+    },
+    Pbkdf2 {
+        c: u32,
+        dklen: usize,
+        prf: String,
+        #[serde(with = "hex")]
+        salt: Vec<u8>,
+    },
+}
+
+/// Decrypts a V3 keystore and returns the raw secp256k1 private key. The
+/// keystore's `address`, when present, must match the decrypted key.
+pub fn decrypt(keystore_json: &str, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let keystore: KeystoreV3 = serde_json::from_str(keystore_json)?;
+    if keystore.version != 3 {
+        bail!("Unsupported keystore version {}", keystore.version);
+    }
+
+    let crypto = &keystore.crypto;
+    if crypto.cipher != CIPHER {
+        bail!("Unsupported keystore cipher {}", crypto.cipher);
+    }
+
+    let derived_key = derive_key(&crypto.kdf, &crypto.kdfparams, password)?;
+    if !bool::from(mac(&derived_key, &crypto.ciphertext).ct_eq(&crypto.mac)) {
+        bail!("Invalid keystore password");
+    }
+
+    let mut private_key = Zeroizing::new(crypto.ciphertext.clone());
+    Aes128Ctr::new_from_slices(&derived_key[..16], &crypto.cipherparams.iv)
+        .map_err(|_| anyhow!("Invalid keystore IV"))?
+        .apply_keystream(&mut private_key);
+
+    if let Some(address) = &keystore.address {
+        let derived = ethereum_address(&private_key)?;
+        if !address.trim_start_matches("0x").eq_ignore_ascii_case(&derived) {
+            bail!("Keystore address {} does not match its key", address);
+        }
+    }
+
+    Ok(private_key)
+}
+
+/// Encrypts `private_key` into a V3 keystore using `kdf` with geth's
+/// standard cost parameters.
+pub fn encrypt(private_key: &[u8], address: &str, password: &str, kdf: Kdf) -> Result<String> {
+    let mut rng = rand::thread_rng();
+    let mut salt = vec![0u8; 32];
+    let mut iv = vec![0u8; 16];
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut iv);
+
+    let (kdf_name, kdfparams) = match kdf {
+        Kdf::Scrypt => (
+            "scrypt",
+            KdfParams::Scrypt {
+                dklen: DERIVED_KEY_LEN,
+                n: 1 << SCRYPT_LOG_N,
+                r: SCRYPT_R,
+                p: SCRYPT_P,
+                salt,
+            },
+        ),
+        Kdf::Pbkdf2 => (
+            "pbkdf2",
+            KdfParams::Pbkdf2 {
+                c: PBKDF2_ROUNDS,
+                dklen: DERIVED_KEY_LEN,
+                prf: "hmac-sha256".to_string(),
+                salt,
+            },
+        ),
+    };
+
+    let derived_key = derive_key(kdf_name, &kdfparams, password)?;
+    let mut ciphertext = private_key.to_vec();
+    Aes128Ctr::new_from_slices(&derived_key[..16], &iv)
+        .map_err(|_| anyhow!("Invalid keystore IV"))?
+        .apply_keystream(&mut ciphertext);
+
+    let keystore = KeystoreV3 {
+        version: 3,
+        id: uuid::Uuid::new_v4().to_string(),
+        address: Some(address.trim_start_matches("0x").to_lowercase()),
+        crypto: CryptoJson {
+            cipher: CIPHER.to_string(),
+            cipherparams: CipherParams { iv },
+            mac: mac(&derived_key, &ciphertext),
+            ciphertext,
+            kdf: kdf_name.to_string(),
+            kdfparams,
+        },
+    };
+
+    Ok(serde_json::to_string(&keystore)?)
+}
+
+fn derive_key(kdf: &str, params: &KdfParams, password: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let dklen = match params {
+        KdfParams::Scrypt { dklen, .. } | KdfParams::Pbkdf2 { dklen, .. } => *dklen,
+    };
+    // The first half keys the cipher and the second the MAC
+    if dklen != DERIVED_KEY_LEN {
+        bail!("Unsupported keystore key length {}", dklen);
+    }
+
+    match (kdf, params) {
+        ("scrypt", KdfParams::Scrypt { dklen, n, r, p, salt }) => {
+            if !n.is_power_of_two() {
+                bail!("Invalid scrypt parameter n = {}", n);
+            }
+            if 128 * u64::from(*n) * u64::from(*r) > MAX_SCRYPT_MEMORY || *p > MAX_SCRYPT_P {
+                bail!("Scrypt parameters n = {}, r = {}, p = {} exceed the supported cost", n, r, p);
+            }
+            let params = scrypt::Params::new(n.trailing_zeros() as u8, *r, *p, *dklen)
+                .map_err(|e| anyhow!("Invalid scrypt parameters: {}", e))?;
+            let mut key = Zeroizing::new(vec![0u8; *dklen]);
+            scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+                .map_err(|e| anyhow!("Scrypt failed: {}", e))?;
+            Ok(key)
+        }
+        ("pbkdf2", KdfParams::Pbkdf2 { c, dklen, prf, salt }) => {
+            if prf != "hmac-sha256" {
+                bail!("Unsupported pbkdf2 PRF {}", prf);
+            }
+            if *c > MAX_PBKDF2_ROUNDS {
+                bail!("Pbkdf2 iteration count {} exceeds the supported cost", c);
+            }
+            let mut key = Zeroizing::new(vec![0u8; *dklen]);
+            pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, *c, &mut key)
+                .map_err(|e| anyhow!("Pbkdf2 failed: {}", e))?;
+            Ok(key)
+        }
+        (kdf, _) => bail!("Unsupported or malformed keystore KDF {}", kdf),
+    }
+}
+
+fn mac(derived_key: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut hasher = Keccak256::new();
+    hasher.update(&derived_key[16..32]);
+    hasher.update(ciphertext);
+    hasher.finalize().to_vec()
+}
+
+fn ethereum_address(private_key: &[u8]) -> Result<String> {
+    let signing_key = SigningKey::from_slice(private_key)?;
+    Ok(hex::encode(ethers::utils::secret_key_to_address(&signing_key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vector from the Web3 Secret Storage Definition
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "6087dab2f9fdbbfaddc31a909735c1e6" },
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    const SCRYPT_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": { "iv": "83dbcc02d8ccb40e466191a123791e0e" },
+            "ciphertext": "d172bf743a674da9cdad04534d56926ef8358534d458fffccd4e6ad2fbde479c",
+            "kdf": "scrypt",
+            "kdfparams": {
+                "dklen": 32,
+                "n": 262144,
+                "r": 1,
+                "p": 8,
+                "salt": "ab0c7876052600dd703518d6fc3fe8984592145b591fc8fb5c6d43190334ba19"
+            },
+            "mac": "2103ac29920d71da29f15d75b4a16dbe95cfd7ff8faea1056c33131d846e3097"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn test_decrypt_spec_vector() {
+        let private_key = decrypt(PBKDF2_KEYSTORE, "testpassword").unwrap();
+        assert_eq!(
+            hex::encode(&*private_key),
+            "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        );
+        assert!(decrypt(PBKDF2_KEYSTORE, "wrongpassword").is_err());
+    }
+
+    #[test]
+    fn test_decrypt_scrypt_spec_vector() {
+        let private_key = decrypt(SCRYPT_KEYSTORE, "testpassword").unwrap();
+        assert_eq!(
+            hex::encode(&*private_key),
+            "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        );
+    }
+
+    #[test]
+    fn test_malformed_kdf_params_rejected() {
+        // A short key would leave no MAC key; excessive costs would exhaust memory
+        let short_key = PBKDF2_KEYSTORE.replace(r#""dklen": 32"#, r#""dklen": 16"#);
+        assert!(decrypt(&short_key, "testpassword").is_err());
+        let huge_n = SCRYPT_KEYSTORE.replace(r#""n": 262144"#, r#""n": 1073741824"#);
+        assert!(decrypt(&huge_n, "testpassword").is_err());
+        let huge_c = PBKDF2_KEYSTORE.replace(r#""c": 262144"#, r#""c": 4294967295"#);
+        assert!(decrypt(&huge_c, "testpassword").is_err());
+    }
+
+    #[test]
+    fn test_pbkdf2_roundtrip() {
+        let private_key = [0x11u8; 32];
+        let address = ethereum_address(&private_key).unwrap();
+
+        let json = encrypt(&private_key, &address, "hunter2", Kdf::Pbkdf2).unwrap();
+        assert_eq!(*decrypt(&json, "hunter2").unwrap(), private_key);
+    }
+}
@@ -1,59 +1,536 @@
-use crate::core::App;
-use anyhow::Result;
+pub mod address_book;
+pub mod backup;
+pub mod encryption;
+pub mod keystore;
+pub mod offchain;
+pub mod offline;
+pub mod passphrase;
+pub mod session;
+pub mod shamir;
+pub mod signer;
+pub mod signing;
+pub mod siwe;
+pub mod solana_keypair;
+pub mod storage;
+pub mod vanity;
+
+use crate::blockchain::{
+    self,
+    bitcoin::{p2wpkh_address, sign_psbt},
+    ens::EnsResolver,
+    offline::{SignedTransaction, UnsignedTransaction},
+    safe::SafeInfo,
+};
+pub use crate::core::ChainType;
+
+use crate::core::{App, Network, WalletConfig};
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
-use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::Keypair;
+use ethers::core::k256::ecdsa::{SigningKey, VerifyingKey};
+use ethers::types::{transaction::eip712::TypedData, Address as EthereumAddress, Signature};
+use solana_sdk::pubkey::Pubkey;
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use zeroize::Zeroizing;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     pub address: String,
-    pub public_key: PublicKey,
+    /// Chain-specific public key: a SEC1 compressed secp256k1 point for
+    /// Ethereum and Bitcoin, a raw ed25519 key for Solana.
+    pub public_key: Vec<u8>,
+    /// Empty for watch-only wallets.
     pub encrypted_private_key: Vec<u8>,
     pub chain_type: ChainType,
     pub balance: f64,
+    #[serde(default)]
+    pub kind: WalletKind,
+    #[serde(default)]
+    pub metadata: WalletMetadata,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum ChainType {
-    Ethereum,
-    Solana,
-    Bitcoin,
+impl Wallet {
+    pub fn is_watch_only(&self) -> bool {
+        !matches!(self.kind, WalletKind::Owned)
+    }
+}
+
+/// Bookkeeping that has no effect on signing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct WalletMetadata {
+    pub label: Option<String>,
+    /// Groups or portfolios the wallet belongs to.
+    pub groups: Vec<String>,
+    /// The Unix epoch for wallets stored before timestamps were recorded.
+    pub created_at: DateTime<Utc>,
+    pub derivation_path: Option<String>,
+    pub source: WalletSource,
+    /// Archived wallets are kept but left out of listings by default.
+    pub archived: bool,
+}
+
+impl WalletMetadata {
+    fn new(source: WalletSource) -> Self {
+        Self {
+            created_at: Utc::now(),
+            source,
+            ..Default::default()
+        }
+    }
+}
+
+/// Where a wallet's key, or for keyless wallets its address, came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum WalletSource {
+    #[default]
+    Generated,
+    Imported,
+    /// Recovered from Shamir backup shares.
+    Recovered,
+    /// Signs through a `RemoteSigner` at `url`; no key is stored here.
+    Remote { url: String },
+}
+
+/// Criteria for `WalletService::list_wallets`. The default matches every
+/// wallet that is not archived.
+#[derive(Debug, Clone, Default)]
+pub struct WalletFilter {
+    pub chain_type: Option<ChainType>,
+    pub group: Option<String>,
+    /// Case-insensitive substring of the label or address.
+    pub search: Option<String>,
+    pub include_archived: bool,
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl WalletFilter {
+    pub fn matches(&self, wallet: &Wallet) -> bool {
+        if wallet.metadata.archived && !self.include_archived {
+            return false;
+        }
+        if self.chain_type.is_some_and(|chain_type| chain_type != wallet.chain_type) {
+            return false;
+        }
+        if let Some(group) = &self.group {
+            if !wallet.metadata.groups.iter().any(|g| g.eq_ignore_ascii_case(group)) {
+                return false;
+            }
+        }
+        if let Some(search) = &self.search {
+            let search = search.to_lowercase();
+            let label_matches = wallet
+                .metadata
+                .label
+                .as_ref()
+                .is_some_and(|label| label.to_lowercase().contains(&search));
+            if !label_matches && !wallet.address.to_lowercase().contains(&search) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum WalletKind {
+    /// Key generated or imported here; the wallet can sign.
+    #[default]
+    Owned,
+    /// Address tracked without its key.
+    WatchOnly,
+    /// Receive address `m/0/index` of an HD account tracked through its xpub.
+    WatchOnlyXpub { xpub: String, index: u32 },
+    /// A Safe multisig contract. It has no key of its own; transactions are
+    /// signed by `threshold` of `owners` and executed via `execTransaction`.
+    Safe { owners: Vec<String>, threshold: u64 },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WalletError {
+    #[error("wallet {0} has no key of its own and cannot sign")]
+    WatchOnly(String),
+    #[error("wallets are locked; unlock them with the passphrase first")]
+    Locked,
 }
 
 pub struct WalletService {
     app: Arc<App>,
     wallets: Arc<RwLock<Vec<Wallet>>>,
+    store: storage::WalletStore,
+    sessions: session::SessionState,
+    address_book: address_book::AddressBook,
 }
 
 impl WalletService {
     pub async fn new(app: Arc<App>) -> Result<Self> {
+        let config = app.get_config().await;
+        let store = storage::WalletStore::connect(&config.wallet.database_url).await?;
+        let wallets = store.load_wallets().await?;
+        Self::check_passphrase_source(&config.wallet, &wallets)?;
+        app.policy_engine().load(store.pool()).await?;
+        let address_book = address_book::AddressBook::new(
+            store.pool(),
+            config.blockchain.bitcoin_network,
+            EnsResolver::from_config(&config.blockchain)?,
+        )
+        .await?;
+
+        let loaded = wallets.len();
+        app.update_state(|state| {
+            state.active_wallets += loaded;
+        }).await;
+
         Ok(Self {
             app,
-            wallets: Arc::new(RwLock::new(Vec::new())),
+            wallets: Arc::new(RwLock::new(wallets)),
+            store,
+            sessions: session::SessionState::default(),
+            address_book,
         })
     }
 
+    /// Fails if the configured passphrase doesn't decrypt the stored keys, as
+    /// when a file or environment passphrase was not updated after
+    /// `rotate_encryption_key`. Prompted passphrases are checked on unlock.
+    fn check_passphrase_source(config: &WalletConfig, wallets: &[Wallet]) -> Result<()> {
+        let source = passphrase::PassphraseSource::parse(&config.encryption_key_source)?;
+        if source == passphrase::PassphraseSource::Prompt {
+            return Ok(());
+        }
+        let Some(wallet) = wallets.iter().find(|w| !w.is_watch_only()) else {
+            return Ok(());
+        };
+
+        let passphrase = Zeroizing::new(passphrase::resolve(config)?);
+        Self::decrypt_private_key(&wallet.encrypted_private_key, &passphrase).map_err(|_| {
+            anyhow!(
+                "The passphrase from encryption_key_source {:?} does not decrypt wallet {}",
+                config.encryption_key_source,
+                wallet.address
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Unlocks signing for up to `duration` (capped at
+    /// `wallet.max_unlock_secs`), or less if the service sits idle for
+    /// `wallet.auto_lock_timeout_secs`. The passphrase is checked against a
+    /// stored key; with no keys stored yet, it becomes the passphrase new
+    /// wallets are encrypted under.
+    pub async fn unlock(&self, passphrase: &str, duration: Duration) -> Result<()> {
+        if passphrase.is_empty() {
+            return Err(anyhow!("Wallet passphrase is empty"));
+        }
+
+        let owned = self
+            .wallets
+            .read()
+            .await
+            .iter()
+            .find(|w| !w.is_watch_only())
+            .map(|w| w.encrypted_private_key.clone());
+        if let Some(encrypted_private_key) = owned {
+            Self::decrypt_private_key(&encrypted_private_key, passphrase)
+                .map_err(|_| anyhow!("Incorrect wallet passphrase"))?;
+        }
+
+        let config = self.app.get_config().await;
+        let duration = duration.min(Duration::from_secs(config.wallet.max_unlock_secs));
+        let idle_timeout = Duration::from_secs(config.wallet.auto_lock_timeout_secs);
+        self.sessions.unlock(passphrase, duration, idle_timeout).await;
+        Ok(())
+    }
+
+    /// Unlocks with the passphrase from `wallet.encryption_key_source`.
+    pub async fn unlock_from_config(&self, duration: Duration) -> Result<()> {
+        let config = self.app.get_config().await;
+        let passphrase = Zeroizing::new(passphrase::resolve(&config.wallet)?);
+        self.unlock(&passphrase, duration).await
+    }
+
+    /// Ends the unlock session, dropping the passphrase. Signers handed out
+    /// earlier share the session and are locked too.
+    pub async fn lock_all(&self) {
+        self.sessions.lock().await;
+    }
+
+    pub async fn is_unlocked(&self) -> bool {
+        self.sessions.is_unlocked().await
+    }
+
     pub async fn create_wallet(&self, chain_type: ChainType) -> Result<Wallet> {
-        let keypair = Keypair::generate(&mut rand::thread_rng());
+        let (private_key, public_key) = Self::generate_keypair(&chain_type);
+        self.store_wallet(&private_key, public_key, chain_type, WalletSource::Generated).await
+    }
+
+    /// Stores the key found by a `vanity::VanitySearch` as a generated
+    /// wallet. The search runs without the wallet unlocked; only this needs a
+    /// session, and can be retried with the same key after unlocking.
+    pub async fn store_vanity_wallet(&self, key: &vanity::VanityKey) -> Result<Wallet> {
+        self.store_wallet(&key.private_key, key.public_key.clone(), key.chain_type, WalletSource::Generated)
+            .await
+    }
+
+    /// Imports an Ethereum key from a Web3 Secret Storage (V3) keystore file,
+    /// as written by geth, MetaMask or Foundry.
+    pub async fn import_keystore(&self, keystore_json: &str, password: &str) -> Result<Wallet> {
+        let private_key = keystore::decrypt(keystore_json, password)?;
+        let public_key = derive_public_key(&private_key, &ChainType::Ethereum)?;
+        self.store_wallet(&private_key, public_key, ChainType::Ethereum, WalletSource::Imported).await
+    }
+
+    /// Exports an Ethereum wallet as a V3 keystore file encrypted with `password`.
+    pub async fn export_keystore(&self, address: &str, password: &str, kdf: keystore::Kdf) -> Result<String> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Ethereum {
+            return Err(anyhow!("Only Ethereum wallets can be exported as keystore files"));
+        }
+
+        keystore::encrypt(&private_key, &wallet.address, password, kdf)
+    }
+
+    /// Imports a Solana CLI keypair file (`id.json`, a JSON array of 64 bytes).
+    pub async fn import_solana_keypair_file(&self, path: impl AsRef<Path>) -> Result<Wallet> {
+        let json = Zeroizing::new(tokio::fs::read_to_string(path).await?);
+        let (private_key, public_key) = solana_keypair::parse_keypair_json(&json)?;
+        self.store_wallet(&private_key, public_key, ChainType::Solana, WalletSource::Imported).await
+    }
+
+    /// Imports a base58-encoded 64-byte secret key, the format Phantom exports.
+    pub async fn import_solana_base58_secret(&self, secret: &str) -> Result<Wallet> {
+        let (private_key, public_key) = solana_keypair::parse_base58_secret(secret)?;
+        self.store_wallet(&private_key, public_key, ChainType::Solana, WalletSource::Imported).await
+    }
+
+    /// Writes a Solana wallet as a Solana CLI keypair file, readable only by
+    /// the current user.
+    pub async fn export_solana_keypair_file(&self, address: &str, path: impl AsRef<Path>) -> Result<()> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Solana {
+            return Err(anyhow!("Wallet {} is not a Solana wallet", address));
+        }
+        let json = solana_keypair::keypair_json(&private_key, &wallet.public_key)?;
+
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(path).await?;
+        tokio::io::AsyncWriteExt::write_all(&mut file, json.as_bytes()).await?;
+
+        Ok(())
+    }
+
+    /// Tracks `address` without its key. The wallet shows up in balances and
+    /// listings like any other, but every signing path rejects it.
+    pub async fn add_watch_only_wallet(&self, address: &str, chain_type: ChainType) -> Result<Wallet> {
+        let config = self.app.get_config().await;
+        let address = normalize_address(address, &chain_type, config.blockchain.bitcoin_network)?;
+        let public_key = match chain_type {
+            ChainType::Solana => address.parse::<Pubkey>()?.to_bytes().to_vec(),
+            // The public key of an EVM or Bitcoin address isn't recoverable
+            ChainType::Ethereum | ChainType::Bitcoin => Vec::new(),
+        };
+
+        self.insert_wallet(Wallet {
+            address,
+            public_key,
+            encrypted_private_key: Vec::new(),
+            chain_type,
+            balance: 0.0,
+            kind: WalletKind::WatchOnly,
+            metadata: WalletMetadata::new(WalletSource::Imported),
+        })
+        .await
+    }
+
+    /// Registers a wallet whose key lives with a remote signing service at
+    /// `url` (see `RemoteSigner`). `signer` returns a remote signer for it;
+    /// the service's own signing methods treat it as watch-only.
+    pub async fn add_remote_wallet(&self, address: &str, url: &str) -> Result<Wallet> {
         let config = self.app.get_config().await;
+        signer::RemoteSigner::new(url, address)?;
+
+        self.insert_wallet(Wallet {
+            address: normalize_address(address, &ChainType::Ethereum, config.blockchain.bitcoin_network)?,
+            public_key: Vec::new(),
+            encrypted_private_key: Vec::new(),
+            chain_type: ChainType::Ethereum,
+            balance: 0.0,
+            kind: WalletKind::WatchOnly,
+            metadata: WalletMetadata::new(WalletSource::Remote { url: url.to_string() }),
+        })
+        .await
+    }
+
+    /// Tracks the first `count` receive addresses (`m/0/i`) of an HD account
+    /// from its extended public key.
+    pub async fn add_watch_only_xpub(&self, xpub: &str, chain_type: ChainType, count: u32) -> Result<Vec<Wallet>> {
+        if chain_type == ChainType::Solana {
+            return Err(anyhow!("Solana accounts use hardened derivation and have no xpub"));
+        }
+
+        let config = self.app.get_config().await;
+        let secp = ::bitcoin::secp256k1::Secp256k1::verification_only();
+        let account = ::bitcoin::bip32::ExtendedPubKey::from_str(xpub)?;
+
+        let mut wallets = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let path = [
+                ::bitcoin::bip32::ChildNumber::from_normal_idx(0)?,
+                ::bitcoin::bip32::ChildNumber::from_normal_idx(index)?,
+            ];
+            let public_key = account.derive_pub(&secp, &path)?.public_key.serialize().to_vec();
+
+            let wallet = self
+                .insert_wallet(Wallet {
+                    address: self.generate_address(&public_key, &chain_type, config.blockchain.bitcoin_network)?,
+                    public_key,
+                    encrypted_private_key: Vec::new(),
+                    chain_type,
+                    balance: 0.0,
+                    kind: WalletKind::WatchOnlyXpub {
+                        xpub: xpub.to_string(),
+                        index,
+                    },
+                    metadata: WalletMetadata {
+                        derivation_path: Some(format!("m/0/{}", index)),
+                        ..WalletMetadata::new(WalletSource::Imported)
+                    },
+                })
+                .await?;
+            wallets.push(wallet);
+        }
+
+        Ok(wallets)
+    }
+
+    /// Returns a `Signer` for the wallet, for handing to blockchain and DeFi
+    /// code in place of the key itself: a remote signer for wallets added with
+    /// `add_remote_wallet`, otherwise one backed by the locally stored key.
+    pub async fn signer(&self, address: &str) -> Result<Arc<dyn signer::Signer>> {
+        let wallet = self
+            .get_wallet(address)
+            .await?
+            .ok_or_else(|| anyhow!("No wallet found for address {}", address))?;
+        if let WalletSource::Remote { url } = &wallet.metadata.source {
+            return Ok(Arc::new(signer::RemoteSigner::new(url, &wallet.address)?));
+        }
+        Ok(Arc::new(signer::LocalSigner::new(
+            wallet,
+            self.wallets.clone(),
+            self.sessions.clone(),
+        )?))
+    }
+
+    /// Splits a wallet's secret key into `share_count` backup shares, any
+    /// `threshold` of which recover the wallet.
+    pub async fn split_wallet_secret(&self, address: &str, threshold: u8, share_count: u8) -> Result<Vec<String>> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        let shares = shamir::split(&private_key, threshold, share_count, wallet.chain_type, &wallet.address)?;
+        Ok(shares.iter().map(|share| share.to_string()).collect())
+    }
+
+    /// Recombines backup shares, re-derives the wallet and registers it. Fails
+    /// if the reconstructed key does not produce the address the shares name.
+    pub async fn recover_wallet_from_shares(&self, shares: &[String]) -> Result<Wallet> {
+        let shares = shares
+            .iter()
+            .map(|share| share.parse::<shamir::Share>())
+            .collect::<Result<Vec<_>>>()?;
+        let private_key = shamir::combine(&shares)?;
+        let chain_type = shares[0].chain_type;
+
+        let config = self.app.get_config().await;
+        let public_key = derive_public_key(&private_key, &chain_type)?;
+        let address = self.generate_address(&public_key, &chain_type, config.blockchain.bitcoin_network)?;
+        if address != shares[0].address {
+            return Err(anyhow!("Recovered key belongs to {}, expected {}", address, shares[0].address));
+        }
+
+        self.store_wallet(&private_key, public_key, chain_type, WalletSource::Recovered).await
+    }
+
+    /// Tracks a deployed Safe so it appears alongside the wallets that own it.
+    /// `info` comes from `BlockchainService::get_safe_info`.
+    pub async fn add_safe_wallet(&self, address: &str, info: &SafeInfo) -> Result<Wallet> {
+        let config = self.app.get_config().await;
+
+        self.insert_wallet(Wallet {
+            address: normalize_address(address, &ChainType::Ethereum, config.blockchain.bitcoin_network)?,
+            public_key: Vec::new(),
+            encrypted_private_key: Vec::new(),
+            chain_type: ChainType::Ethereum,
+            balance: 0.0,
+            kind: WalletKind::Safe {
+                owners: info.owners.iter().map(|owner| format!("{:?}", owner)).collect(),
+                threshold: info.threshold,
+            },
+            metadata: WalletMetadata::new(WalletSource::Imported),
+        })
+        .await
+    }
+
+    /// Looks up `address` and decrypts its private key. Watch-only wallets and
+    /// a locked service are rejected here, so every signing and export path
+    /// refuses them.
+    async fn signing_key(&self, address: &str) -> Result<(Wallet, Zeroizing<Vec<u8>>)> {
+        let wallet = self
+            .get_wallet(address)
+            .await?
+            .ok_or_else(|| anyhow!("No wallet found for address {}", address))?;
+        if wallet.is_watch_only() {
+            return Err(WalletError::WatchOnly(wallet.address).into());
+        }
+
+        let passphrase = self.sessions.passphrase().await?;
+        let private_key = Self::decrypt_private_key(&wallet.encrypted_private_key, &passphrase)?;
+
+        Ok((wallet, private_key))
+    }
+
+    /// Encrypts `private_key` and registers the wallet. Every wallet, whether
+    /// generated or imported, goes through here.
+    async fn store_wallet(
+        &self,
+        private_key: &[u8],
+        public_key: Vec<u8>,
+        chain_type: ChainType,
+        source: WalletSource,
+    ) -> Result<Wallet> {
+        let config = self.app.get_config().await;
+        let passphrase = self.sessions.passphrase().await?;
         
         // Encrypt private key
-        let encrypted_private_key = self.encrypt_private_key(
-            keypair.secret.as_bytes(),
-            &config.wallet.encryption_key,
-        )?;
+        let encrypted_private_key = Self::encrypt_private_key(private_key, &passphrase)?;
 
         let wallet = Wallet {
-            address: self.generate_address(&keypair.public, &chain_type),
-            public_key: keypair.public,
+            address: self.generate_address(&public_key, &chain_type, config.blockchain.bitcoin_network)?,
+            public_key,
             encrypted_private_key,
             chain_type,
             balance: 0.0,
+            kind: WalletKind::Owned,
+            metadata: WalletMetadata::new(source),
         };
 
+        self.insert_wallet(wallet).await
+    }
+
+    async fn insert_wallet(&self, wallet: Wallet) -> Result<Wallet> {
         let mut wallets = self.wallets.write().await;
+        if wallets.iter().any(|w| w.address == wallet.address) {
+            return Err(anyhow!("Wallet {} already exists", wallet.address));
+        }
+        self.store.insert_wallet(&wallet).await?;
         wallets.push(wallet.clone());
         
         self.app.update_state(|state| {
@@ -63,49 +540,471 @@ impl WalletService {
         Ok(wallet)
     }
 
+    /// Re-encrypts every stored private key from `old_key` to `new_key`.
+    ///
+    /// All keys are decrypted and re-encrypted before anything is written, and
+    /// the database update runs in one transaction, so a wrong `old_key` or a
+    /// failure part-way leaves every wallet under the old passphrase. Any
+    /// unlock session ends, so signing needs an unlock with `new_key`.
+    ///
+    /// Refused while the passphrase is `wallet.encryption_key` itself, which
+    /// would go on holding `old_key`. A file or environment passphrase must
+    /// be updated to `new_key` before the next start.
+    pub async fn rotate_encryption_key(&self, old_key: &str, new_key: &str) -> Result<()> {
+        if new_key.is_empty() {
+            return Err(anyhow!("New passphrase must not be empty"));
+        }
+        let config = self.app.get_config().await;
+        let source = passphrase::PassphraseSource::parse(&config.wallet.encryption_key_source)?;
+        if source == passphrase::PassphraseSource::Config {
+            return Err(anyhow!(
+                "Cannot rotate a passphrase stored as wallet.encryption_key; \
+                 switch encryption_key_source to env:, file: or prompt first"
+            ));
+        }
+
+        let mut wallets = self.wallets.write().await;
+        let mut rotated = Vec::with_capacity(wallets.len());
+        for wallet in wallets.iter().filter(|w| !w.encrypted_private_key.is_empty()) {
+            let private_key = Self::decrypt_private_key(&wallet.encrypted_private_key, old_key)
+                .map_err(|_| anyhow!("Current passphrase is incorrect"))?;
+            rotated.push(Wallet {
+                encrypted_private_key: Self::encrypt_private_key(&private_key, new_key)?,
+                ..wallet.clone()
+            });
+        }
+
+        self.store.update_wallets(&rotated).await?;
+
+        for updated in rotated {
+            if let Some(wallet) = wallets.iter_mut().find(|w| w.address == updated.address) {
+                *wallet = updated;
+            }
+        }
+        self.sessions.lock().await;
+
+        Ok(())
+    }
+
+    pub fn address_book(&self) -> &address_book::AddressBook {
+        &self.address_book
+    }
+
+    /// Warnings to show before sending `request`: a recipient never saved as a
+    /// contact, or one that mimics a contact or one of our own wallets. An
+    /// ENS recipient is checked by the address it resolves to.
+    pub async fn check_recipient(
+        &self,
+        request: &blockchain::TransactionRequest,
+    ) -> Result<Vec<address_book::RecipientWarning>> {
+        let chain_type = request.chain_type;
+        let own_addresses: Vec<String> = self
+            .wallets
+            .read()
+            .await
+            .iter()
+            .filter(|w| w.chain_type == chain_type)
+            .map(|w| w.address.clone())
+            .collect();
+
+        self.address_book
+            .check_recipient(&request.to, chain_type, &own_addresses)
+            .await
+    }
+
+    /// Looks up a wallet by address in any form `normalize_address` accepts,
+    /// such as checksummed Ethereum addresses.
     pub async fn get_wallet(&self, address: &str) -> Result<Option<Wallet>> {
+        let bitcoin_network = self.app.get_config().await.blockchain.bitcoin_network;
         let wallets = self.wallets.read().await;
-        Ok(wallets.iter().find(|w| w.address == address).cloned())
+        Ok(wallets.iter().find(|w| is_wallet_address(w, address, bitcoin_network)).cloned())
+    }
+
+    /// Returns the wallets matching `filter`, in the order they were added.
+    pub async fn list_wallets(&self, filter: &WalletFilter) -> Vec<Wallet> {
+        self.wallets
+            .read()
+            .await
+            .iter()
+            .filter(|wallet| filter.matches(wallet))
+            .skip(filter.offset)
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    pub async fn set_wallet_label(&self, address: &str, label: Option<String>) -> Result<Wallet> {
+        let label = label.map(|label| label.trim().to_string()).filter(|label| !label.is_empty());
+        self.update_metadata(address, |metadata| metadata.label = label).await
+    }
+
+    pub async fn set_wallet_groups(&self, address: &str, groups: Vec<String>) -> Result<Wallet> {
+        let mut groups: Vec<String> = groups
+            .iter()
+            .map(|group| group.trim().to_string())
+            .filter(|group| !group.is_empty())
+            .collect();
+        groups.sort();
+        groups.dedup();
+        self.update_metadata(address, |metadata| metadata.groups = groups).await
+    }
+
+    pub async fn set_wallet_archived(&self, address: &str, archived: bool) -> Result<Wallet> {
+        self.update_metadata(address, |metadata| metadata.archived = archived).await
+    }
+
+    async fn update_metadata(&self, address: &str, update: impl FnOnce(&mut WalletMetadata)) -> Result<Wallet> {
+        let bitcoin_network = self.app.get_config().await.blockchain.bitcoin_network;
+        let mut wallets = self.wallets.write().await;
+        let wallet = wallets
+            .iter_mut()
+            .find(|w| is_wallet_address(w, address, bitcoin_network))
+            .ok_or_else(|| anyhow!("No wallet found for address {}", address))?;
+
+        let mut updated = wallet.clone();
+        update(&mut updated.metadata);
+        self.store.update_wallets(std::slice::from_ref(&updated)).await?;
+        *wallet = updated.clone();
+
+        Ok(updated)
+    }
+
+    /// Signs a transaction exported by `BlockchainService::export_unsigned_transaction`
+    /// with the key of the wallet it was built for. Never touches the network.
+    pub async fn sign_offline(&self, unsigned: &UnsignedTransaction) -> Result<SignedTransaction> {
+        let (wallet, private_key) = self.signing_key(&unsigned.from).await?;
+        offline::sign_transaction(&wallet, &private_key, unsigned)
+    }
+
+    /// Signs `message` with an Ethereum wallet as EIP-191 `personal_sign`.
+    pub async fn sign_message(&self, address: &str, message: &[u8]) -> Result<Signature> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Ethereum {
+            return Err(anyhow!("Wallet {} is not an Ethereum wallet", address));
+        }
+
+        signing::sign_message(&private_key, message)
+    }
+
+    /// Signs EIP-712 typed data (`eth_signTypedData_v4`) with an Ethereum wallet.
+    pub async fn sign_typed_data(&self, address: &str, typed_data: &TypedData) -> Result<Signature> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Ethereum {
+            return Err(anyhow!("Wallet {} is not an Ethereum wallet", address));
+        }
+
+        signing::sign_typed_data(&private_key, typed_data)
+    }
+
+    /// Signs a Sign-In with Ethereum message with the wallet it names.
+    pub async fn sign_siwe(&self, message: &siwe::SiweMessage) -> Result<Signature> {
+        let address = format!("{:?}", message.address);
+        self.sign_message(&address, message.to_string().as_bytes()).await
+    }
+
+    /// Checks an EIP-191 signature. Works for any address, not just our wallets.
+    pub fn verify_signature(&self, address: &str, message: &[u8], signature: &Signature) -> Result<bool> {
+        signing::verify_signature(address, message, signature)
+    }
+
+    /// Signs `message` with a Solana wallet in the off-chain message format
+    /// and returns the base58 signature.
+    pub async fn sign_solana_message(&self, address: &str, message: &[u8]) -> Result<String> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Solana {
+            return Err(anyhow!("Wallet {} is not a Solana wallet", address));
+        }
+
+        let signature = offchain::sign_offchain_message(&private_key, &wallet.public_key, message)?;
+        Ok(signature.to_string())
     }
 
-    pub async fn list_wallets(&self) -> Vec<Wallet> {
-        self.wallets.read().await.clone()
+    /// Checks a base58 off-chain message signature against a base58 public key.
+    pub fn verify_solana_message(&self, public_key: &str, message: &[u8], signature: &str) -> Result<bool> {
+        offchain::verify_offchain_message(public_key, message, signature)
     }
 
-    fn encrypt_private_key(&self, private_key: &[u8], encryption_key: &str) -> Result<Vec<u8>> {
-        // Implement proper encryption here
-        // This is a placeholder implementation
-        Ok(private_key.to_vec())
+    /// Adds this wallet's signatures to every input of `psbt` it can spend,
+    /// leaving finalization to the caller so other cosigners can still sign.
+    pub async fn sign_psbt(&self, address: &str, psbt: &mut ::bitcoin::psbt::Psbt) -> Result<usize> {
+        let (wallet, private_key) = self.signing_key(address).await?;
+        if wallet.chain_type != ChainType::Bitcoin {
+            return Err(anyhow!("Wallet {} is not a Bitcoin wallet", address));
+        }
+
+        sign_psbt(psbt, &private_key)
     }
 
-    fn generate_address(&self, public_key: &PublicKey, chain_type: &ChainType) -> String {
+    fn generate_keypair(chain_type: &ChainType) -> (Zeroizing<Vec<u8>>, Vec<u8>) {
         match chain_type {
-            ChainType::Ethereum => format!("0x{}", hex::encode(&public_key.to_bytes()[..20])),
-            ChainType::Solana => bs58::encode(public_key.to_bytes()).into_string(),
-            ChainType::Bitcoin => {
-                // Implement Bitcoin address generation
-                "btc_address".to_string()
+            ChainType::Solana => {
+                let keypair = Keypair::generate(&mut rand::thread_rng());
+                (Zeroizing::new(keypair.secret.to_bytes().to_vec()), keypair.public.to_bytes().to_vec())
+            }
+            ChainType::Ethereum | ChainType::Bitcoin => {
+                let signing_key = SigningKey::random(&mut rand::thread_rng());
+                let public_key = signing_key.verifying_key().to_encoded_point(true);
+                (Zeroizing::new(signing_key.to_bytes().to_vec()), public_key.as_bytes().to_vec())
             }
         }
     }
 
+    fn encrypt_private_key(private_key: &[u8], encryption_key: &str) -> Result<Vec<u8>> {
+        encryption::encrypt(private_key, encryption_key)
+    }
+
+    pub(crate) fn decrypt_private_key(encrypted_private_key: &[u8], encryption_key: &str) -> Result<Zeroizing<Vec<u8>>> {
+        Ok(Zeroizing::new(encryption::decrypt(encrypted_private_key, encryption_key)?))
+    }
+
+    fn generate_address(&self, public_key: &[u8], chain_type: &ChainType, bitcoin_network: Network) -> Result<String> {
+        Ok(match chain_type {
+            ChainType::Ethereum => {
+                let verifying_key = VerifyingKey::from_sec1_bytes(public_key)?;
+                format!("{:?}", ethers::utils::public_key_to_address(&verifying_key))
+            }
+            ChainType::Solana => bs58::encode(public_key).into_string(),
+            ChainType::Bitcoin => {
+                let network = bitcoin_network.to_bitcoin()?;
+                p2wpkh_address(public_key, network)?.to_string()
+            }
+        })
+    }
+
     pub async fn run(&self) -> Result<()> {
         // Implement wallet service main loop
         Ok(())
     }
 }
 
+/// Derives the public key stored on a `Wallet` from its private key.
+pub fn derive_public_key(private_key: &[u8], chain_type: &ChainType) -> Result<Vec<u8>> {
+    Ok(match chain_type {
+        ChainType::Solana => {
+            let secret = ed25519_dalek::SecretKey::from_bytes(private_key)
+                .map_err(|e| anyhow!("Invalid ed25519 secret key: {}", e))?;
+            ed25519_dalek::PublicKey::from(&secret).to_bytes().to_vec()
+        }
+        ChainType::Ethereum | ChainType::Bitcoin => {
+            let signing_key = SigningKey::from_slice(private_key)?;
+            signing_key.verifying_key().to_encoded_point(true).as_bytes().to_vec()
+        }
+    })
+}
+
+/// Validates `address` for `chain_type` and returns it in the form wallets
+/// are stored under: lowercase hex for Ethereum, base58 for Solana and the
+/// configured network's encoding for Bitcoin.
+pub fn normalize_address(address: &str, chain_type: &ChainType, bitcoin_network: Network) -> Result<String> {
+    let address = address.trim();
+    Ok(match chain_type {
+        ChainType::Ethereum => format!("{:?}", address.parse::<EthereumAddress>()?),
+        ChainType::Solana => address.parse::<Pubkey>()?.to_string(),
+        ChainType::Bitcoin => {
+            let network = bitcoin_network.to_bitcoin()?;
+            ::bitcoin::Address::from_str(address)?.require_network(network)?.to_string()
+        }
+    })
+}
+
+/// Whether `address` is `wallet`'s address once normalized for its chain.
+fn is_wallet_address(wallet: &Wallet, address: &str, bitcoin_network: Network) -> bool {
+    normalize_address(address, &wallet.chain_type, bitcoin_network).is_ok_and(|address| address == wallet.address)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    async fn test_app() -> Arc<App> {
+        let mut config = App::new().await.unwrap().get_config().await;
+        config.wallet.database_url = "sqlite::memory:".to_string();
+        config.wallet.encryption_key_source = "config".to_string();
+        Arc::new(App::from_config(config))
+    }
+
+    async fn unlocked_service() -> WalletService {
+        let wallet_service = WalletService::new(test_app().await).await.unwrap();
+        wallet_service.unlock_from_config(Duration::from_secs(60)).await.unwrap();
+        wallet_service
+    }
+
     #[tokio::test]
     async fn test_wallet_creation() {
-        let app = Arc::new(App::new().await.unwrap());
-        let wallet_service = WalletService::new(app).await.unwrap();
+        let wallet_service = unlocked_service().await;
         
         let wallet = wallet_service.create_wallet(ChainType::Ethereum).await.unwrap();
         assert!(!wallet.address.is_empty());
         assert_eq!(wallet.chain_type, ChainType::Ethereum);
+
+        // Found by its checksummed form as well as the stored lowercase one
+        let checksummed = ethers::utils::to_checksum(&wallet.address.parse().unwrap(), None);
+        let found = wallet_service.get_wallet(&checksummed).await.unwrap().unwrap();
+        assert_eq!(found.address, wallet.address);
+        let labelled = wallet_service.set_wallet_label(&checksummed, Some("Main".to_string())).await.unwrap();
+        assert_eq!(labelled.address, wallet.address);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_watch_only_wallet_cannot_sign() {
+        let app = test_app().await;
+        let wallet_service = WalletService::new(app).await.unwrap();
+
+        let address = "0x52908400098527886E0F7030069857D2E4169EE7";
+        let wallet = wallet_service.add_watch_only_wallet(address, ChainType::Ethereum).await.unwrap();
+        assert!(wallet.is_watch_only());
+        assert_eq!(wallet.address, address.to_lowercase());
+
+        let err = wallet_service
+            .export_keystore(&wallet.address, "password", keystore::Kdf::Pbkdf2)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref::<WalletError>(), Some(WalletError::WatchOnly(_))));
+    }
+
+    #[tokio::test]
+    async fn test_locked_service_cannot_sign() {
+        let wallet_service = unlocked_service().await;
+        let wallet = wallet_service.create_wallet(ChainType::Ethereum).await.unwrap();
+        wallet_service.sign_message(&wallet.address, b"hello").await.unwrap();
+        let signer = wallet_service.signer(&wallet.address).await.unwrap();
+
+        wallet_service.lock_all().await;
+        let err = wallet_service.sign_message(&wallet.address, b"hello").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<WalletError>(), Some(WalletError::Locked)));
+        let err = signer.sign_message(b"hello").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<WalletError>(), Some(WalletError::Locked)));
+
+        assert!(wallet_service.unlock("wrong", Duration::from_secs(60)).await.is_err());
+        assert!(!wallet_service.is_unlocked().await);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_encryption_key() {
+        let config_sourced = unlocked_service().await;
+        config_sourced.create_wallet(ChainType::Ethereum).await.unwrap();
+        let config_key = config_sourced.app.get_config().await.wallet.encryption_key;
+        assert!(config_sourced.rotate_encryption_key(&config_key, "new passphrase").await.is_err());
+
+        let mut config = test_app().await.get_config().await;
+        config.wallet.encryption_key_source = "prompt".to_string();
+        let wallet_service = WalletService::new(Arc::new(App::from_config(config))).await.unwrap();
+        let old_key = "old passphrase";
+        wallet_service.unlock(old_key, Duration::from_secs(60)).await.unwrap();
+        let wallet = wallet_service.create_wallet(ChainType::Ethereum).await.unwrap();
+        let signature = wallet_service.sign_message(&wallet.address, b"before").await.unwrap();
+
+        assert!(wallet_service.rotate_encryption_key("wrong", "new passphrase").await.is_err());
+        wallet_service.rotate_encryption_key(old_key, "new passphrase").await.unwrap();
+
+        let rotated = wallet_service.get_wallet(&wallet.address).await.unwrap().unwrap();
+        assert_ne!(rotated.encrypted_private_key, wallet.encrypted_private_key);
+        assert!(WalletService::decrypt_private_key(&rotated.encrypted_private_key, old_key).is_err());
+
+        // Rotation ends the session; the key itself is unchanged
+        assert!(!wallet_service.is_unlocked().await);
+        wallet_service.unlock("new passphrase", Duration::from_secs(60)).await.unwrap();
+        let after = wallet_service.sign_message(&wallet.address, b"before").await.unwrap();
+        assert_eq!(after, signature);
+
+        // A passphrase left at the old key fails the next start
+        let mut config = wallet_service.app.get_config().await.wallet;
+        config.encryption_key_source = "config".to_string();
+        config.encryption_key = old_key.to_string();
+        assert!(WalletService::check_passphrase_source(&config, &[rotated.clone()]).is_err());
+        config.encryption_key = "new passphrase".to_string();
+        WalletService::check_passphrase_source(&config, &[rotated]).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_recipient() {
+        let wallet_service = WalletService::new(test_app().await).await.unwrap();
+        let contact = wallet_service
+            .address_book()
+            .add_contact(
+                "Alice",
+                vec![address_book::ContactAddress {
+                    chain_type: ChainType::Ethereum,
+                    address: "0x52908400098527886E0F7030069857D2E4169EE7".to_string(),
+                    ens_name: None,
+                }],
+                None,
+                vec!["Friends".to_string()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(contact.tags, vec!["friends".to_string()]);
+
+        let request = |to: &str| blockchain::TransactionRequest {
+            from: String::new(),
+            to: to.to_string(),
+            amount: 1.0,
+            chain_type: ChainType::Ethereum,
+            gas_limit: None,
+            gas_price: None,
+            data: None,
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            nonce_account: None,
+            nonce_authority: None,
+            fee_rate: None,
+        };
+
+        let warnings = wallet_service
+            .check_recipient(&request("0x52908400098527886e0f7030069857d2e4169ee7"))
+            .await
+            .unwrap();
+        assert!(warnings.is_empty());
+
+        let warnings = wallet_service
+            .check_recipient(&request("0x5290a1b2c3d4e5f60718293a4b5c6d7e8f909ee7"))
+            .await
+            .unwrap();
+        assert_eq!(warnings[0], address_book::RecipientWarning::FirstTime);
+        assert!(matches!(
+            &warnings[1],
+            address_book::RecipientWarning::Lookalike { contact: Some(name), .. } if name == "Alice"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_list_wallets_filter() {
+        let wallet_service = unlocked_service().await;
+        let ethereum = wallet_service.create_wallet(ChainType::Ethereum).await.unwrap();
+        let solana = wallet_service.create_wallet(ChainType::Solana).await.unwrap();
+        let archived = wallet_service.create_wallet(ChainType::Ethereum).await.unwrap();
+
+        wallet_service.set_wallet_label(&ethereum.address, Some("Treasury".to_string())).await.unwrap();
+        wallet_service.set_wallet_groups(&solana.address, vec!["Trading".to_string()]).await.unwrap();
+        wallet_service.set_wallet_archived(&archived.address, true).await.unwrap();
+        assert_eq!(ethereum.metadata.source, WalletSource::Generated);
+
+        let addresses = |wallets: Vec<Wallet>| wallets.into_iter().map(|w| w.address).collect::<Vec<_>>();
+        assert_eq!(
+            addresses(wallet_service.list_wallets(&WalletFilter::default()).await),
+            vec![ethereum.address.clone(), solana.address.clone()]
+        );
+
+        let filter = WalletFilter {
+            search: Some("treas".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(addresses(wallet_service.list_wallets(&filter).await), vec![ethereum.address.clone()]);
+
+        let filter = WalletFilter {
+            group: Some("trading".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(addresses(wallet_service.list_wallets(&filter).await), vec![solana.address.clone()]);
+
+        let filter = WalletFilter {
+            chain_type: Some(ChainType::Ethereum),
+            include_archived: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            addresses(wallet_service.list_wallets(&filter).await),
+            vec![ethereum.address, archived.address]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Result};
+use solana_sdk::{
+    offchain_message::OffchainMessage,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use zeroize::Zeroizing;
+
+/// Off-chain message header version produced by `sign_offchain_message`.
+pub const OFFCHAIN_MESSAGE_VERSION: u8 = 0;
+
+/// Signs `message` in the Solana off-chain message format: the
+/// `\xffsolana offchain` signing domain, header version and format byte
+/// (restricted ASCII, limited or extended UTF-8, chosen from the content)
+/// followed by the length-prefixed message. The domain prefix guarantees the
+/// signature can never be replayed as a transaction signature.
+pub fn sign_offchain_message(private_key: &[u8], public_key: &[u8], message: &[u8]) -> Result<Signature> {
+    let keypair_bytes = Zeroizing::new([private_key, public_key].concat());
+    let keypair = Keypair::from_bytes(&keypair_bytes)?;
+    let offchain_message = OffchainMessage::new(OFFCHAIN_MESSAGE_VERSION, message)?;
+    Ok(offchain_message.sign(&keypair)?)
+}
+
+/// Verifies a base58 signature over `message` in the off-chain message
+/// format against a base58 public key.
+pub fn verify_offchain_message(public_key: &str, message: &[u8], signature: &str) -> Result<bool> {
+    let public_key = public_key
+        .parse::<Pubkey>()
+        .map_err(|e| anyhow!("Invalid public key {}: {}", public_key, e))?;
+    let signature = signature
+        .parse::<Signature>()
+        .map_err(|e| anyhow!("Invalid signature: {}", e))?;
+
+    let offchain_message = OffchainMessage::new(OFFCHAIN_MESSAGE_VERSION, message)?;
+    Ok(offchain_message.verify(&public_key, &signature)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn test_sign_and_verify_offchain_message() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+        let pubkey = keypair.pubkey().to_string();
+
+        let signature = sign_offchain_message(&bytes[..32], &bytes[32..], b"Sign in to Example").unwrap();
+        assert!(verify_offchain_message(&pubkey, b"Sign in to Example", &signature.to_string()).unwrap());
+        assert!(!verify_offchain_message(&pubkey, b"Sign in to Evil", &signature.to_string()).unwrap());
+
+        // The raw message signature must not verify: the header is part of what's signed
+        let raw = keypair.sign_message(b"Sign in to Example");
+        assert!(!verify_offchain_message(&pubkey, b"Sign in to Example", &raw.to_string()).unwrap());
+    }
+}
@@ -0,0 +1,173 @@
+use super::{ChainType, Wallet, WalletError};
+use crate::blockchain::{
+    bitcoin::{finalize_psbt, sign_psbt},
+    offline::{SignedTransaction, UnsignedTransaction, OFFLINE_FORMAT_VERSION},
+};
+use anyhow::{anyhow, bail, Result};
+use ethers::{
+    signers::{LocalWallet, Signer as _},
+    types::transaction::eip2718::TypedTransaction,
+};
+use solana_sdk::{
+    message::Message,
+    signature::Keypair as SolanaKeypair,
+    transaction::Transaction as SolanaTransaction,
+};
+use zeroize::Zeroizing;
+
+/// Signs `unsigned` with `private_key`, the decrypted key of `wallet`.
+///
+/// This is a pure function so it can run on an air-gapped machine: it only
+/// checks that the transaction was built for this wallet and produces the
+/// raw signed transaction for `BlockchainService::broadcast_signed_transaction`.
+pub fn sign_transaction(
+    wallet: &Wallet,
+    private_key: &[u8],
+    unsigned: &UnsignedTransaction,
+) -> Result<SignedTransaction> {
+    if unsigned.version != OFFLINE_FORMAT_VERSION {
+        bail!("Unsupported offline transaction format version {}", unsigned.version);
+    }
+    if wallet.is_watch_only() {
+        return Err(WalletError::WatchOnly(wallet.address.clone()).into());
+    }
+    if !unsigned.from.eq_ignore_ascii_case(&wallet.address) {
+        bail!("Transaction is for {}, not wallet {}", unsigned.from, wallet.address);
+    }
+
+    if unsigned.chain_type != wallet.chain_type {
+        bail!("Wallet {} cannot sign {} transactions", wallet.address, unsigned.chain_type);
+    }
+
+    let payload = match unsigned.chain_type {
+        ChainType::Ethereum => sign_ethereum(private_key, &unsigned.payload)?,
+        ChainType::Solana => sign_solana(wallet, private_key, &unsigned.payload)?,
+        ChainType::Bitcoin => sign_bitcoin(private_key, &unsigned.payload)?,
+    };
+
+    Ok(SignedTransaction {
+        version: OFFLINE_FORMAT_VERSION,
+        chain_type: unsigned.chain_type,
+        from: unsigned.from.clone(),
+        payload,
+    })
+}
+
+fn sign_ethereum(private_key: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let tx: TypedTransaction = serde_json::from_slice(payload)?;
+    let chain_id = tx
+        .chain_id()
+        .ok_or_else(|| anyhow!("Unsigned Ethereum transaction has no chain ID"))?;
+
+    let signer = LocalWallet::from_bytes(private_key)?.with_chain_id(chain_id.as_u64());
+    if tx.from().is_some_and(|from| *from != signer.address()) {
+        bail!("Transaction sender does not match the signing key");
+    }
+
+    let signature = signer.sign_transaction_sync(&tx)?;
+    Ok(tx.rlp_signed(&signature).to_vec())
+}
+
+fn sign_solana(wallet: &Wallet, private_key: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let message: Message = bincode::deserialize(payload)?;
+    let keypair_bytes = Zeroizing::new([private_key, &wallet.public_key].concat());
+    let keypair = SolanaKeypair::from_bytes(&keypair_bytes)?;
+
+    let mut transaction = SolanaTransaction::new_unsigned(message);
+    let recent_blockhash = transaction.message.recent_blockhash;
+    transaction.try_sign(&[&keypair], recent_blockhash)?;
+
+    Ok(bincode::serialize(&transaction)?)
+}
+
+fn sign_bitcoin(private_key: &[u8], payload: &[u8]) -> Result<Vec<u8>> {
+    let mut psbt = ::bitcoin::psbt::Psbt::deserialize(payload)?;
+    if sign_psbt(&mut psbt, private_key)? != psbt.inputs.len() {
+        bail!("PSBT spends inputs this wallet cannot sign");
+    }
+    finalize_psbt(&mut psbt)?;
+
+    Ok(::bitcoin::consensus::serialize(&psbt.extract_tx()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        types::{Address, Eip1559TransactionRequest},
+        utils::rlp::Rlp,
+    };
+    use solana_sdk::{hash::Hash, signature::Signer, system_instruction};
+
+    #[test]
+    fn test_sign_ethereum_offline() {
+        let key = LocalWallet::new(&mut rand::thread_rng());
+        let wallet = Wallet {
+            address: format!("{:?}", key.address()),
+            public_key: key.signer().verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+            encrypted_private_key: Vec::new(),
+            chain_type: ChainType::Ethereum,
+            balance: 0.0,
+            kind: super::super::WalletKind::Owned,
+            metadata: Default::default(),
+        };
+
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(key.address())
+            .to(Address::random())
+            .value(1_000_000_000u64)
+            .nonce(7u64)
+            .gas(21_000u64)
+            .max_fee_per_gas(30_000_000_000u64)
+            .max_priority_fee_per_gas(1_000_000_000u64)
+            .chain_id(11_155_111)
+            .into();
+        let mut unsigned = UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: ChainType::Ethereum,
+            from: wallet.address.clone(),
+            payload: serde_json::to_vec(&tx).unwrap(),
+        };
+
+        let signed = sign_transaction(&wallet, &key.signer().to_bytes(), &unsigned).unwrap();
+        let (decoded, signature) = TypedTransaction::decode_signed(&Rlp::new(&signed.payload)).unwrap();
+        assert_eq!(decoded.chain_id(), Some(11_155_111u64.into()));
+        assert_eq!(signature.recover(decoded.sighash()).unwrap(), key.address());
+
+        // A transaction naming another sender is refused
+        let mut other = tx;
+        other.set_from(Address::random());
+        unsigned.payload = serde_json::to_vec(&other).unwrap();
+        assert!(sign_transaction(&wallet, &key.signer().to_bytes(), &unsigned).is_err());
+    }
+
+    #[test]
+    fn test_sign_solana_offline() {
+        let keypair = SolanaKeypair::new();
+        let wallet = Wallet {
+            address: keypair.pubkey().to_string(),
+            public_key: keypair.pubkey().to_bytes().to_vec(),
+            encrypted_private_key: Vec::new(),
+            chain_type: ChainType::Solana,
+            balance: 0.0,
+            kind: super::super::WalletKind::Owned,
+            metadata: Default::default(),
+        };
+
+        let mut message = Message::new(
+            &[system_instruction::transfer(&keypair.pubkey(), &solana_sdk::pubkey::Pubkey::new_unique(), 1)],
+            Some(&keypair.pubkey()),
+        );
+        message.recent_blockhash = Hash::new_unique();
+        let unsigned = UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: ChainType::Solana,
+            from: wallet.address.clone(),
+            payload: bincode::serialize(&message).unwrap(),
+        };
+
+        let signed = sign_transaction(&wallet, &keypair.secret().to_bytes(), &unsigned).unwrap();
+        let transaction: SolanaTransaction = bincode::deserialize(&signed.payload).unwrap();
+        assert!(transaction.verify().is_ok());
+    }
+}
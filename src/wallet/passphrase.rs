@@ -0,0 +1,71 @@
+use crate::core::WalletConfig;
+use anyhow::{anyhow, bail, Result};
+
+/// Where the key encryption passphrase comes from, set by
+/// `wallet.encryption_key_source`:
+///
+/// - `config`: the `wallet.encryption_key` value itself
+/// - `env:NAME`: the environment variable `NAME`
+/// - `file:PATH`: the first line of the file at `PATH`
+/// - `prompt`: asked for on the terminal without echo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassphraseSource {
+    Config,
+    Env(String),
+    File(String),
+    Prompt,
+}
+
+impl PassphraseSource {
+    pub fn parse(source: &str) -> Result<Self> {
+        Ok(match source.split_once(':') {
+            Some(("env", name)) if !name.is_empty() => Self::Env(name.to_string()),
+            Some(("file", path)) if !path.is_empty() => Self::File(path.to_string()),
+            None if source == "config" => Self::Config,
+            None if source == "prompt" => Self::Prompt,
+            _ => bail!("Invalid encryption_key_source {:?}", source),
+        })
+    }
+}
+
+/// Resolves the passphrase configured in `config`.
+pub fn resolve(config: &WalletConfig) -> Result<String> {
+    let passphrase = match PassphraseSource::parse(&config.encryption_key_source)? {
+        PassphraseSource::Config => config.encryption_key.clone(),
+        PassphraseSource::Env(name) => {
+            std::env::var(&name).map_err(|_| anyhow!("Environment variable {} is not set", name))?
+        }
+        PassphraseSource::File(path) => std::fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Cannot read passphrase file {}: {}", path, e))?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        PassphraseSource::Prompt => rpassword::prompt_password("Wallet passphrase: ")?,
+    };
+
+    if passphrase.is_empty() {
+        bail!("Wallet passphrase is empty");
+    }
+    Ok(passphrase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_source() {
+        assert_eq!(PassphraseSource::parse("config").unwrap(), PassphraseSource::Config);
+        assert_eq!(
+            PassphraseSource::parse("env:WALLET_PASSPHRASE").unwrap(),
+            PassphraseSource::Env("WALLET_PASSPHRASE".to_string())
+        );
+        assert_eq!(
+            PassphraseSource::parse("file:/run/secrets/wallet").unwrap(),
+            PassphraseSource::File("/run/secrets/wallet".to_string())
+        );
+        assert!(PassphraseSource::parse("env:").is_err());
+        assert!(PassphraseSource::parse("vault").is_err());
+    }
+}
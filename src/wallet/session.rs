@@ -0,0 +1,111 @@
+use super::WalletError;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use zeroize::Zeroizing;
+
+/// An unlocked wallet passphrase. It stays usable until `expires_at` or until
+/// it has gone unused for `idle_timeout`, whichever comes first, and is wiped
+/// from memory when dropped.
+struct UnlockSession {
+    passphrase: Zeroizing<String>,
+    expires_at: Instant,
+    idle_timeout: Duration,
+    last_used: Instant,
+}
+
+impl UnlockSession {
+    fn deadline(&self) -> Instant {
+        self.expires_at.min(self.last_used + self.idle_timeout)
+    }
+}
+
+/// Shared handle to the wallet service's unlock session. Clones refer to the
+/// same session, so locking through one handle locks every signer.
+#[derive(Clone, Default)]
+pub struct SessionState {
+    inner: Arc<Mutex<Option<UnlockSession>>>,
+}
+
+impl SessionState {
+    /// Opens a session for `duration`, replacing any current one, and spawns
+    /// a task that drops the passphrase as soon as the session expires.
+    pub async fn unlock(&self, passphrase: &str, duration: Duration, idle_timeout: Duration) {
+        let now = Instant::now();
+        *self.inner.lock().await = Some(UnlockSession {
+            passphrase: Zeroizing::new(passphrase.to_string()),
+            expires_at: now + duration,
+            idle_timeout,
+            last_used: now,
+        });
+
+        let weak = Arc::downgrade(&self.inner);
+        tokio::spawn(async move {
+            loop {
+                let Some(inner) = weak.upgrade() else { return };
+                let mut session = inner.lock().await;
+                let deadline = match session.as_ref() {
+                    Some(session) => session.deadline(),
+                    None => return,
+                };
+                if deadline <= Instant::now() {
+                    *session = None;
+                    return;
+                }
+                drop(session);
+                drop(inner);
+                tokio::time::sleep_until(deadline).await;
+            }
+        });
+    }
+
+    pub async fn lock(&self) {
+        self.inner.lock().await.take();
+    }
+
+    pub async fn is_unlocked(&self) -> bool {
+        matches!(self.inner.lock().await.as_ref(), Some(session) if session.deadline() > Instant::now())
+    }
+
+    /// Returns the unlocked passphrase and resets the idle timer, or
+    /// `WalletError::Locked` if there is no live session.
+    pub async fn passphrase(&self) -> Result<Zeroizing<String>, WalletError> {
+        let mut inner = self.inner.lock().await;
+        let now = Instant::now();
+        match inner.as_mut() {
+            Some(session) if session.deadline() > now => {
+                session.last_used = now;
+                Ok(session.passphrase.clone())
+            }
+            _ => {
+                inner.take();
+                Err(WalletError::Locked)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        let sessions = SessionState::default();
+        assert!(matches!(sessions.passphrase().await, Err(WalletError::Locked)));
+
+        sessions
+            .unlock("passphrase", Duration::from_secs(600), Duration::from_secs(60))
+            .await;
+        tokio::time::advance(Duration::from_secs(45)).await;
+        assert_eq!(sessions.passphrase().await.unwrap().as_str(), "passphrase");
+
+        // Use resets the idle timer, but not the overall duration
+        tokio::time::advance(Duration::from_secs(45)).await;
+        assert!(sessions.is_unlocked().await);
+        tokio::time::advance(Duration::from_secs(61)).await;
+        assert!(!sessions.is_unlocked().await);
+        assert!(matches!(sessions.passphrase().await, Err(WalletError::Locked)));
+    }
+}
@@ -0,0 +1,242 @@
+use super::ChainType;
+use anyhow::{anyhow, bail, Result};
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use zeroize::Zeroizing;
+
+const SHARE_PREFIX: &str = "dwshare1";
+const SHARE_VERSION: u8 = 1;
+const CHECKSUM_LEN: usize = 4;
+
+/// One share of a wallet secret split with Shamir's scheme over GF(256).
+///
+/// Besides the share value, every share carries the metadata needed to
+/// recombine it: the split identifier (shares from different splits never
+/// mix), threshold and share count, plus the chain and address of the wallet
+/// so recovery can confirm it re-derived the right key. The text encoding
+/// ends in a checksum so transcription errors are caught per share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    pub identifier: u16,
+    pub threshold: u8,
+    pub share_count: u8,
+    /// x coordinate of this share, 1..=share_count.
+    pub index: u8,
+    pub chain_type: ChainType,
+    pub address: String,
+    pub value: Vec<u8>,
+}
+
+/// Splits `secret` into `share_count` shares, any `threshold` of which
+/// reconstruct it.
+pub fn split(
+    secret: &[u8],
+    threshold: u8,
+    share_count: u8,
+    chain_type: ChainType,
+    address: &str,
+) -> Result<Vec<Share>> {
+    if threshold == 0 || threshold > share_count {
+        bail!("Threshold must be between 1 and the number of shares");
+    }
+
+    let mut rng = rand::thread_rng();
+    let identifier = rng.gen::<u16>();
+
+    // One random polynomial per secret byte, with the byte as constant term
+    let mut coefficients = Zeroizing::new(vec![vec![0u8; threshold as usize]; secret.len()]);
+    for (polynomial, byte) in coefficients.iter_mut().zip(secret) {
+        polynomial[0] = *byte;
+        rng.fill_bytes(&mut polynomial[1..]);
+    }
+
+    Ok((1..=share_count)
+        .map(|x| Share {
+            identifier,
+            threshold,
+            share_count,
+            index: x,
+            chain_type,
+            address: address.to_string(),
+            value: coefficients.iter().map(|polynomial| evaluate(polynomial, x)).collect(),
+        })
+        .collect())
+}
+
+/// Reconstructs the secret from at least `threshold` shares of one split.
+pub fn combine(shares: &[Share]) -> Result<Zeroizing<Vec<u8>>> {
+    let first = shares.first().ok_or_else(|| anyhow!("No shares provided"))?;
+
+    let mut indices = Vec::with_capacity(shares.len());
+    for share in shares {
+        if share.identifier != first.identifier
+            || share.threshold != first.threshold
+            || share.chain_type != first.chain_type
+            || share.address != first.address
+            || share.value.len() != first.value.len()
+        {
+            bail!("Shares belong to different backups");
+        }
+        if share.index == 0 || indices.contains(&share.index) {
+            bail!("Duplicate or invalid share index {}", share.index);
+        }
+        indices.push(share.index);
+    }
+    if shares.len() < first.threshold as usize {
+        bail!("{} shares required, {} provided", first.threshold, shares.len());
+    }
+
+    let shares = &shares[..first.threshold as usize];
+    Ok(Zeroizing::new(
+        (0..first.value.len())
+            .map(|i| interpolate_at_zero(shares.iter().map(|share| (share.index, share.value[i]))))
+            .collect(),
+    ))
+}
+
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bytes = vec![SHARE_VERSION];
+        bytes.extend_from_slice(&self.identifier.to_be_bytes());
+        bytes.extend_from_slice(&[self.threshold, self.share_count, self.index, chain_tag(self.chain_type)]);
+        bytes.push(self.address.len() as u8);
+        bytes.extend_from_slice(self.address.as_bytes());
+        bytes.extend_from_slice(&self.value);
+        let checksum = Sha256::digest(&bytes);
+        bytes.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+
+        write!(f, "{}{}", SHARE_PREFIX, hex::encode(bytes))
+    }
+}
+
+impl FromStr for Share {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let encoded = s
+            .trim()
+            .strip_prefix(SHARE_PREFIX)
+            .ok_or_else(|| anyhow!("Not a wallet backup share"))?;
+        let bytes = hex::decode(encoded)?;
+        if bytes.len() < 8 + CHECKSUM_LEN {
+            bail!("Share is truncated");
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if Sha256::digest(body)[..CHECKSUM_LEN] != *checksum {
+            bail!("Share checksum mismatch; check it was copied correctly");
+        }
+        if body[0] != SHARE_VERSION {
+            bail!("Unsupported share version {}", body[0]);
+        }
+
+        let address_len = body[7] as usize;
+        if body.len() < 8 + address_len {
+            bail!("Share is truncated");
+        }
+
+        Ok(Share {
+            identifier: u16::from_be_bytes([body[1], body[2]]),
+            threshold: body[3],
+            share_count: body[4],
+            index: body[5],
+            chain_type: chain_from_tag(body[6])?,
+            address: String::from_utf8(body[8..8 + address_len].to_vec())?,
+            value: body[8 + address_len..].to_vec(),
+        })
+    }
+}
+
+fn chain_tag(chain_type: ChainType) -> u8 {
+    match chain_type {
+        ChainType::Ethereum => 0,
+        ChainType::Solana => 1,
+        ChainType::Bitcoin => 2,
+    }
+}
+
+fn chain_from_tag(tag: u8) -> Result<ChainType> {
+    match tag {
+        0 => Ok(ChainType::Ethereum),
+        1 => Ok(ChainType::Solana),
+        2 => Ok(ChainType::Bitcoin),
+        other => Err(anyhow!("Unknown chain tag {}", other)),
+    }
+}
+
+/// Multiplication in GF(2^8) with the AES reduction polynomial x^8 + x^4 + x^3 + x + 1.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse, as a^254 since the group has order 255.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exponent = 254u8;
+    while exponent != 0 {
+        if exponent & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exponent >>= 1;
+    }
+    result
+}
+
+fn evaluate(polynomial: &[u8], x: u8) -> u8 {
+    // Horner's rule; addition in GF(2^8) is XOR
+    polynomial.iter().rev().fold(0, |acc, coefficient| gf_mul(acc, x) ^ coefficient)
+}
+
+fn interpolate_at_zero(points: impl Iterator<Item = (u8, u8)> + Clone) -> u8 {
+    points.clone().fold(0, |acc, (xi, yi)| {
+        let basis = points.clone().filter(|(xj, _)| *xj != xi).fold(1, |basis, (xj, _)| {
+            // L_i(0) = prod x_j / (x_j - x_i), and subtraction is XOR
+            gf_mul(basis, gf_mul(xj, gf_inv(xj ^ xi)))
+        });
+        acc ^ gf_mul(yi, basis)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_combine() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split(&secret, 3, 5, ChainType::Ethereum, "0xabc").unwrap();
+
+        assert_eq!(*combine(&shares[..3]).unwrap(), secret);
+        assert_eq!(*combine(&[shares[4].clone(), shares[1].clone(), shares[2].clone()]).unwrap(), secret);
+        assert!(combine(&shares[..2]).is_err());
+
+        let encoded: Vec<String> = shares.iter().map(Share::to_string).collect();
+        let decoded: Vec<Share> = encoded.iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(decoded, shares);
+    }
+
+    #[test]
+    fn test_corrupted_share_rejected() {
+        let shares = split(&[7u8; 32], 2, 3, ChainType::Solana, "So1ana").unwrap();
+        let mut encoded = shares[0].to_string();
+        let last = encoded.pop().unwrap();
+        encoded.push(if last == '0' { '1' } else { '0' });
+        assert!(encoded.parse::<Share>().is_err());
+    }
+}
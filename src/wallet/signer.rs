@@ -0,0 +1,240 @@
+use super::{offchain, offline, session::SessionState, signing, ChainType, Wallet, WalletError, WalletService};
+use crate::blockchain::offline::{SignedTransaction, UnsignedTransaction, OFFLINE_FORMAT_VERSION};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, JsonRpcClient},
+    types::{transaction::eip2718::TypedTransaction, transaction::eip712::TypedData, Address, Bytes, Signature},
+};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use zeroize::Zeroizing;
+
+/// Something that can sign on behalf of one address. Blockchain and DeFi
+/// code take a `Signer` instead of key material, so keys may live in this
+/// process, on a remote signing service or anywhere else.
+#[async_trait]
+pub trait Signer: Send + Sync {
+    fn address(&self) -> &str;
+
+    fn chain_type(&self) -> ChainType;
+
+    /// Signs a transaction prepared by `BlockchainService::export_unsigned_transaction`.
+    async fn sign_transaction(&self, unsigned: &UnsignedTransaction) -> Result<SignedTransaction>;
+
+    /// Signs an arbitrary message: EIP-191 on Ethereum, the off-chain message
+    /// format on Solana.
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>>;
+
+    /// Signs EIP-712 typed data. Only meaningful for Ethereum signers.
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature>;
+}
+
+/// Signs with a wallet's locally stored key, decrypting it only for the
+/// duration of each signing call. It shares the wallet service's wallets and
+/// unlock session, so it follows passphrase rotation and `lock_all`.
+pub struct LocalSigner {
+    wallet: Wallet,
+    wallets: Arc<RwLock<Vec<Wallet>>>,
+    sessions: SessionState,
+}
+
+impl LocalSigner {
+    pub(crate) fn new(
+        wallet: Wallet,
+        wallets: Arc<RwLock<Vec<Wallet>>>,
+        sessions: SessionState,
+    ) -> Result<Self> {
+        if wallet.is_watch_only() {
+            return Err(WalletError::WatchOnly(wallet.address).into());
+        }
+        Ok(Self {
+            wallet,
+            wallets,
+            sessions,
+        })
+    }
+
+    async fn private_key(&self) -> Result<Zeroizing<Vec<u8>>> {
+        let passphrase = self.sessions.passphrase().await?;
+        let wallets = self.wallets.read().await;
+        let wallet = wallets
+            .iter()
+            .find(|w| w.address == self.wallet.address)
+            .ok_or_else(|| anyhow!("Wallet {} no longer exists", self.wallet.address))?;
+
+        WalletService::decrypt_private_key(&wallet.encrypted_private_key, &passphrase)
+    }
+}
+
+#[async_trait]
+impl Signer for LocalSigner {
+    fn address(&self) -> &str {
+        &self.wallet.address
+    }
+
+    fn chain_type(&self) -> ChainType {
+        self.wallet.chain_type
+    }
+
+    async fn sign_transaction(&self, unsigned: &UnsignedTransaction) -> Result<SignedTransaction> {
+        let private_key = self.private_key().await?;
+        offline::sign_transaction(&self.wallet, &private_key, unsigned)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let private_key = self.private_key().await?;
+        match self.wallet.chain_type {
+            ChainType::Ethereum => Ok(signing::sign_message(&private_key, message)?.to_vec()),
+            ChainType::Solana => {
+                let signature = offchain::sign_offchain_message(&private_key, &self.wallet.public_key, message)?;
+                Ok(signature.as_ref().to_vec())
+            }
+            ChainType::Bitcoin => bail!("Message signing is not supported for Bitcoin wallets"),
+        }
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature> {
+        if self.wallet.chain_type != ChainType::Ethereum {
+            bail!("Typed data can only be signed by Ethereum wallets");
+        }
+        let private_key = self.private_key().await?;
+        signing::sign_typed_data(&private_key, typed_data)
+    }
+}
+
+/// Signs through a remote service speaking the Ethereum JSON-RPC signing
+/// methods (`eth_signTransaction`, `eth_sign`, `eth_signTypedData_v4`), as
+/// exposed by web3signer, Clef and similar key managers.
+pub struct RemoteSigner {
+    address: String,
+    client: Http,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, address: &str) -> Result<Self> {
+        Ok(Self {
+            address: address.to_string(),
+            client: Http::from_str(url)?,
+        })
+    }
+}
+
+#[async_trait]
+impl Signer for RemoteSigner {
+    fn address(&self) -> &str {
+        &self.address
+    }
+
+    fn chain_type(&self) -> ChainType {
+        ChainType::Ethereum
+    }
+
+    async fn sign_transaction(&self, unsigned: &UnsignedTransaction) -> Result<SignedTransaction> {
+        if unsigned.chain_type != ChainType::Ethereum {
+            bail!("Remote signer only signs Ethereum transactions");
+        }
+        let tx: TypedTransaction = serde_json::from_slice(&unsigned.payload)?;
+        let address: Address = self.address.parse()?;
+        if unsigned.from.parse::<Address>()? != address || tx.from().is_some_and(|from| *from != address) {
+            bail!("Transaction is from {}, not remote signer {}", unsigned.from, self.address);
+        }
+        let raw: Bytes = self.client.request("eth_signTransaction", [tx]).await?;
+
+        Ok(SignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: unsigned.chain_type,
+            from: unsigned.from.clone(),
+            payload: raw.to_vec(),
+        })
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Vec<u8>> {
+        let signature: Bytes = self
+            .client
+            .request("eth_sign", (&self.address, Bytes::from(message.to_vec())))
+            .await?;
+        Ok(signature.to_vec())
+    }
+
+    async fn sign_typed_data(&self, typed_data: &TypedData) -> Result<Signature> {
+        let signature: Bytes = self
+            .client
+            .request("eth_signTypedData_v4", (&self.address, typed_data))
+            .await?;
+        Signature::try_from(signature.as_ref()).map_err(|e| anyhow!("Invalid signature from remote signer: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const COW_KEY: &str = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4";
+    const COW_ADDRESS: &str = "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826";
+
+    /// Stand-in remote signer answering a single `eth_sign` request.
+    async fn serve_one_eth_sign(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        let body = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((_, body)) = text.split_once("\r\n\r\n") {
+                if serde_json::from_str::<serde_json::Value>(body).is_ok() {
+                    break body.to_string();
+                }
+            }
+        };
+
+        let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(request["method"], "eth_sign");
+        let message = hex::decode(request["params"][1].as_str().unwrap().trim_start_matches("0x")).unwrap();
+        let signature = signing::sign_message(&hex::decode(COW_KEY).unwrap(), &message).unwrap();
+
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": format!("0x{}", signature),
+        })
+        .to_string();
+        let reply = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            response.len(),
+            response
+        );
+        stream.write_all(reply.as_bytes()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_sign_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve_one_eth_sign(listener));
+
+        let signer = RemoteSigner::new(&url, COW_ADDRESS).unwrap();
+        let signature = signer.sign_message(b"hello").await.unwrap();
+        let signature = Signature::try_from(signature.as_slice()).unwrap();
+        assert!(signing::verify_signature(COW_ADDRESS, b"hello", &signature).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_refuses_other_senders() {
+        let signer = RemoteSigner::new("http://127.0.0.1:1", COW_ADDRESS).unwrap();
+        let tx: TypedTransaction = ethers::types::TransactionRequest::new().from(Address::random()).into();
+        let unsigned = UnsignedTransaction {
+            version: OFFLINE_FORMAT_VERSION,
+            chain_type: ChainType::Ethereum,
+            from: COW_ADDRESS.to_string(),
+            payload: serde_json::to_vec(&tx).unwrap(),
+        };
+
+        let error = signer.sign_transaction(&unsigned).await.unwrap_err();
+        assert!(error.to_string().contains("not remote signer"));
+    }
+}
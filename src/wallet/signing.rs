@@ -0,0 +1,111 @@
+use anyhow::Result;
+use ethers::{
+    signers::LocalWallet,
+    types::{
+        transaction::eip712::{Eip712, TypedData},
+        Address, Signature, H256,
+    },
+    utils::hash_message,
+};
+
+/// Signs `message` as an EIP-191 `personal_sign` message (version 0x45).
+pub fn sign_message(private_key: &[u8], message: &[u8]) -> Result<Signature> {
+    let signer = LocalWallet::from_bytes(private_key)?;
+    Ok(signer.sign_hash(hash_message(message))?)
+}
+
+/// Signs EIP-712 typed data as `eth_signTypedData_v4` does.
+pub fn sign_typed_data(private_key: &[u8], typed_data: &TypedData) -> Result<Signature> {
+    let signer = LocalWallet::from_bytes(private_key)?;
+    let hash = typed_data.encode_eip712()?;
+    Ok(signer.sign_hash(H256::from(hash))?)
+}
+
+/// Checks that `signature` is `address`'s EIP-191 signature over `message`.
+pub fn verify_signature(address: &str, message: &[u8], signature: &Signature) -> Result<bool> {
+    let address = address.parse::<Address>()?;
+    Ok(signature.recover(hash_message(message)).is_ok_and(|signer| signer == address))
+}
+
+/// Checks that `signature` is `address`'s EIP-712 signature over `typed_data`.
+pub fn verify_typed_data(address: &str, typed_data: &TypedData, signature: &Signature) -> Result<bool> {
+    let address = address.parse::<Address>()?;
+    let hash = H256::from(typed_data.encode_eip712()?);
+    Ok(signature.recover(hash).is_ok_and(|signer| signer == address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // keccak256("cow"), the signer used throughout the EIP-712 examples
+    const COW_KEY: &str = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4";
+    const COW_ADDRESS: &str = "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826";
+
+    const MAIL_TYPED_DATA: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" }
+            ],
+            "Person": [
+                { "name": "name", "type": "string" },
+                { "name": "wallet", "type": "address" }
+            ],
+            "Mail": [
+                { "name": "from", "type": "Person" },
+                { "name": "to", "type": "Person" },
+                { "name": "contents", "type": "string" }
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+            "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    #[test]
+    fn test_eip191_vector() {
+        assert_eq!(
+            hex::encode(hash_message("Hello World")),
+            "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2"
+        );
+
+        let private_key = hex::decode(COW_KEY).unwrap();
+        let signature = sign_message(&private_key, b"Hello World").unwrap();
+        assert!(verify_signature(COW_ADDRESS, b"Hello World", &signature).unwrap());
+        assert!(!verify_signature(COW_ADDRESS, b"Hello Bob", &signature).unwrap());
+    }
+
+    #[test]
+    fn test_eip712_mail_vector() {
+        let typed_data: TypedData = serde_json::from_str(MAIL_TYPED_DATA).unwrap();
+        assert_eq!(
+            hex::encode(typed_data.encode_eip712().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let private_key = hex::decode(COW_KEY).unwrap();
+        let signature = sign_typed_data(&private_key, &typed_data).unwrap();
+        assert_eq!(signature.v, 28);
+        assert_eq!(
+            format!("{:x}", signature.r),
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d"
+        );
+        assert_eq!(
+            format!("{:x}", signature.s),
+            "7299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562"
+        );
+        assert!(verify_typed_data(COW_ADDRESS, &typed_data, &signature).unwrap());
+    }
+}
@@ -0,0 +1,353 @@
+use super::signing;
+use chrono::{DateTime, Duration, FixedOffset, SecondsFormat, Utc};
+use ethers::{
+    types::{Address, Signature},
+    utils::to_checksum,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+
+const HEADER_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+const VERSION: &str = "1";
+const MIN_NONCE_LENGTH: usize = 8;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SiweError {
+    #[error("malformed SIWE message: {0}")]
+    Malformed(String),
+    #[error("SIWE domain {found} does not match expected {expected}")]
+    DomainMismatch { expected: String, found: String },
+    #[error("SIWE nonce does not match the issued nonce")]
+    NonceMismatch,
+    #[error("SIWE message expired at {0}")]
+    Expired(String),
+    #[error("SIWE message is not valid before {0}")]
+    NotYetValid(String),
+    #[error("SIWE signature was not produced by {0}")]
+    InvalidSignature(String),
+}
+
+/// A Sign-In with Ethereum message as specified by EIP-4361.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiweMessage {
+    pub scheme: Option<String>,
+    pub domain: String,
+    pub address: Address,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: DateTime<FixedOffset>,
+    pub expiration_time: Option<DateTime<FixedOffset>>,
+    pub not_before: Option<DateTime<FixedOffset>>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    /// Creates a message issued now for `address` to sign in to `domain`,
+    /// optionally expiring after `expires_in`.
+    pub fn new(
+        domain: &str,
+        address: Address,
+        uri: &str,
+        chain_id: u64,
+        nonce: &str,
+        expires_in: Option<Duration>,
+    ) -> Self {
+        let issued_at = Utc::now().fixed_offset();
+        Self {
+            scheme: None,
+            domain: domain.to_string(),
+            address,
+            statement: None,
+            uri: uri.to_string(),
+            version: VERSION.to_string(),
+            chain_id,
+            nonce: nonce.to_string(),
+            issued_at,
+            expiration_time: expires_in.map(|duration| issued_at + duration),
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    pub fn with_statement(mut self, statement: &str) -> Self {
+        self.statement = Some(statement.to_string());
+        self
+    }
+
+    pub fn with_resources(mut self, resources: Vec<String>) -> Self {
+        self.resources = resources;
+        self
+    }
+
+    /// Checks the message's domain, nonce and validity window against the
+    /// server's expectations at time `now`.
+    pub fn validate(&self, domain: &str, nonce: Option<&str>, now: DateTime<Utc>) -> Result<(), SiweError> {
+        if self.domain != domain {
+            return Err(SiweError::DomainMismatch {
+                expected: domain.to_string(),
+                found: self.domain.clone(),
+            });
+        }
+        if nonce.is_some_and(|nonce| nonce != self.nonce) {
+            return Err(SiweError::NonceMismatch);
+        }
+        if let Some(expiration_time) = self.expiration_time {
+            if now >= expiration_time {
+                return Err(SiweError::Expired(format_time(&expiration_time)));
+            }
+        }
+        if let Some(not_before) = self.not_before {
+            if now < not_before {
+                return Err(SiweError::NotYetValid(format_time(&not_before)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns a random alphanumeric nonce suitable for a SIWE message.
+pub fn generate_nonce() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(17)
+        .map(char::from)
+        .collect()
+}
+
+/// Verifies a SIWE message received from a client: parses it, checks domain,
+/// nonce and time bounds, and checks the EIP-191 signature over the exact
+/// text that was signed. Returns the parsed message on success.
+pub fn verify(
+    message: &str,
+    signature: &Signature,
+    domain: &str,
+    nonce: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<SiweMessage, SiweError> {
+    let parsed = message.parse::<SiweMessage>()?;
+    parsed.validate(domain, nonce, now)?;
+
+    let address = to_checksum(&parsed.address, None);
+    match signing::verify_signature(&address, message.as_bytes(), signature) {
+        Ok(true) => Ok(parsed),
+        _ => Err(SiweError::InvalidSignature(address)),
+    }
+}
+
+fn format_time(time: &DateTime<FixedOffset>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.scheme {
+            Some(scheme) => writeln!(f, "{}://{}{}", scheme, self.domain, HEADER_SUFFIX)?,
+            None => writeln!(f, "{}{}", self.domain, HEADER_SUFFIX)?,
+        }
+        writeln!(f, "{}", to_checksum(&self.address, None))?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", format_time(&self.issued_at))?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", format_time(expiration_time))?;
+        }
+        if let Some(not_before) = &self.not_before {
+            write!(f, "\nNot Before: {}", format_time(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(message: &str) -> Result<Self, Self::Err> {
+        let malformed = |reason: &str| SiweError::Malformed(reason.to_string());
+        let mut lines = message.split('\n').peekable();
+
+        let header = lines
+            .next()
+            .and_then(|line| line.strip_suffix(HEADER_SUFFIX))
+            .ok_or_else(|| malformed("missing header"))?;
+        let (scheme, domain) = match header.split_once("://") {
+            Some((scheme, domain)) => (Some(scheme.to_string()), domain.to_string()),
+            None => (None, header.to_string()),
+        };
+        if domain.is_empty() {
+            return Err(malformed("empty domain"));
+        }
+
+        let address_line = lines.next().ok_or_else(|| malformed("missing address"))?;
+        let address = address_line
+            .parse::<Address>()
+            .map_err(|_| malformed("invalid address"))?;
+        if to_checksum(&address, None) != address_line {
+            return Err(malformed("address is not EIP-55 checksummed"));
+        }
+
+        expect_blank(&mut lines)?;
+        let statement = match lines.next() {
+            Some("") => None,
+            Some(statement) => {
+                expect_blank(&mut lines)?;
+                Some(statement.to_string())
+            }
+            None => return Err(malformed("missing URI")),
+        };
+
+        let uri = tagged(&mut lines, "URI: ")?.to_string();
+        let version = tagged(&mut lines, "Version: ")?.to_string();
+        if version != VERSION {
+            return Err(malformed("unsupported version"));
+        }
+        let chain_id = tagged(&mut lines, "Chain ID: ")?
+            .parse::<u64>()
+            .map_err(|_| malformed("invalid chain ID"))?;
+        let nonce = tagged(&mut lines, "Nonce: ")?.to_string();
+        if nonce.len() < MIN_NONCE_LENGTH || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(malformed("nonce must be at least 8 alphanumeric characters"));
+        }
+        let issued_at = parse_time(tagged(&mut lines, "Issued At: ")?)?;
+
+        let expiration_time = optional_tagged(&mut lines, "Expiration Time: ").map(parse_time).transpose()?;
+        let not_before = optional_tagged(&mut lines, "Not Before: ").map(parse_time).transpose()?;
+        let request_id = optional_tagged(&mut lines, "Request ID: ").map(str::to_string);
+
+        let mut resources = Vec::new();
+        if lines.next_if_eq(&"Resources:").is_some() {
+            while let Some(line) = lines.next() {
+                let resource = line
+                    .strip_prefix("- ")
+                    .ok_or_else(|| malformed("invalid resource line"))?;
+                resources.push(resource.to_string());
+            }
+        }
+        if lines.next().is_some() {
+            return Err(malformed("unexpected trailing content"));
+        }
+
+        Ok(Self {
+            scheme,
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time,
+            not_before,
+            request_id,
+            resources,
+        })
+    }
+}
+
+fn expect_blank<'a>(lines: &mut impl Iterator<Item = &'a str>) -> Result<(), SiweError> {
+    match lines.next() {
+        Some("") => Ok(()),
+        _ => Err(SiweError::Malformed("expected blank line".to_string())),
+    }
+}
+
+fn tagged<'a>(lines: &mut impl Iterator<Item = &'a str>, tag: &str) -> Result<&'a str, SiweError> {
+    lines
+        .next()
+        .and_then(|line| line.strip_prefix(tag))
+        .ok_or_else(|| SiweError::Malformed(format!("missing \"{}\" field", tag.trim_end_matches(": "))))
+}
+
+fn optional_tagged<'a, I: Iterator<Item = &'a str>>(lines: &mut Peekable<I>, tag: &str) -> Option<&'a str> {
+    lines
+        .next_if(|line| line.starts_with(tag))
+        .map(|line| &line[tag.len()..])
+}
+
+fn parse_time(value: &str) -> Result<DateTime<FixedOffset>, SiweError> {
+    DateTime::parse_from_rfc3339(value).map_err(|_| SiweError::Malformed(format!("invalid timestamp {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example message from EIP-4361
+    const SPEC_MESSAGE: &str = "service.org wants you to sign in with your Ethereum account:
+0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2
+
+I accept the ServiceOrg Terms of Service: https://service.org/tos
+
+URI: https://service.org/login
+Version: 1
+Chain ID: 1
+Nonce: 32891756
+Issued At: 2021-09-30T16:25:24Z
+Resources:
+- ipfs://bafybeiemxf5abjwjbikoz4mc3a3dla6ual3jsgpdr4cjr3oz3evfyavhwq/
+- https://example.com/my-web2-claim.json";
+
+    const COW_KEY: &str = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4";
+
+    #[test]
+    fn test_spec_message_roundtrip() {
+        let message = SPEC_MESSAGE.parse::<SiweMessage>().unwrap();
+        assert_eq!(message.domain, "service.org");
+        assert_eq!(message.nonce, "32891756");
+        assert_eq!(message.resources.len(), 2);
+        assert_eq!(message.to_string(), SPEC_MESSAGE);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let private_key = hex::decode(COW_KEY).unwrap();
+        let address = ethers::signers::LocalWallet::from_bytes(&private_key).unwrap().address();
+        let nonce = generate_nonce();
+        let message = SiweMessage::new(
+            "example.com",
+            address,
+            "https://example.com/login",
+            1,
+            &nonce,
+            Some(Duration::minutes(5)),
+        )
+        .with_statement("Sign in to Example");
+
+        let text = message.to_string();
+        let signature = signing::sign_message(&private_key, text.as_bytes()).unwrap();
+
+        let now = Utc::now();
+        assert_eq!(verify(&text, &signature, "example.com", Some(&nonce), now).unwrap(), message);
+        assert!(matches!(
+            verify(&text, &signature, "evil.com", Some(&nonce), now),
+            Err(SiweError::DomainMismatch { .. })
+        ));
+        assert!(matches!(
+            verify(&text, &signature, "example.com", Some(&nonce), now + Duration::minutes(10)),
+            Err(SiweError::Expired(_))
+        ));
+    }
+}
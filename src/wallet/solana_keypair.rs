@@ -0,0 +1,78 @@
+use anyhow::{anyhow, bail, Result};
+use ed25519_dalek::{PublicKey, SecretKey};
+use zeroize::Zeroizing;
+
+/// Length of a Solana keypair: 32-byte ed25519 secret followed by its public key.
+pub const KEYPAIR_LENGTH: usize = 64;
+
+/// Splits a 64-byte Solana keypair into its secret and public halves,
+/// checking that the public half is really derived from the secret.
+pub fn parse_keypair_bytes(bytes: &[u8]) -> Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+    if bytes.len() != KEYPAIR_LENGTH {
+        bail!("Solana keypair must be {} bytes, got {}", KEYPAIR_LENGTH, bytes.len());
+    }
+
+    let (secret, public) = bytes.split_at(32);
+    let secret_key = SecretKey::from_bytes(secret).map_err(|e| anyhow!("Invalid secret key: {}", e))?;
+    let derived = PublicKey::from(&secret_key);
+    if derived.as_bytes() != public {
+        bail!(
+            "Keypair public key {} does not match its secret key (expected {})",
+            bs58::encode(public).into_string(),
+            bs58::encode(derived.as_bytes()).into_string()
+        );
+    }
+
+    Ok((Zeroizing::new(secret.to_vec()), public.to_vec()))
+}
+
+/// Parses the JSON byte array written by `solana-keygen` (`id.json`).
+pub fn parse_keypair_json(json: &str) -> Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+    let bytes: Zeroizing<Vec<u8>> = Zeroizing::new(
+        serde_json::from_str(json).map_err(|e| anyhow!("Keypair file is not a JSON byte array: {}", e))?,
+    );
+    parse_keypair_bytes(&bytes)
+}
+
+/// Parses a base58 secret key as exported by Phantom and other browser wallets.
+pub fn parse_base58_secret(secret: &str) -> Result<(Zeroizing<Vec<u8>>, Vec<u8>)> {
+    let bytes = Zeroizing::new(
+        bs58::decode(secret.trim())
+            .into_vec()
+            .map_err(|e| anyhow!("Invalid base58 secret key: {}", e))?,
+    );
+    parse_keypair_bytes(&bytes)
+}
+
+/// Encodes a keypair in the `solana-keygen` JSON byte array format.
+pub fn keypair_json(secret: &[u8], public: &[u8]) -> Result<Zeroizing<String>> {
+    let keypair = Zeroizing::new([secret, public].concat());
+    Ok(Zeroizing::new(serde_json::to_string(&*keypair)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    #[test]
+    fn test_keypair_formats_roundtrip() {
+        let keypair = Keypair::new();
+        let bytes = keypair.to_bytes();
+
+        let json = keypair_json(&bytes[..32], &bytes[32..]).unwrap();
+        let (secret, public) = parse_keypair_json(&json).unwrap();
+        assert_eq!(*secret, &bytes[..32]);
+        assert_eq!(public, keypair.pubkey().to_bytes());
+
+        let (_, public) = parse_base58_secret(&keypair.to_base58_string()).unwrap();
+        assert_eq!(public, keypair.pubkey().to_bytes());
+    }
+
+    #[test]
+    fn test_mismatched_pubkey_rejected() {
+        let mut bytes = Keypair::new().to_bytes();
+        bytes[40] ^= 0xff;
+        assert!(parse_keypair_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use super::Wallet;
+use anyhow::Result;
+use sqlx::any::{AnyPoolOptions, AnyPool};
+use std::path::Path;
+
+/// Persists wallets in SQLite or Postgres, chosen by the URL scheme. Each
+/// wallet is stored as a JSON document keyed by address, so new `Wallet`
+/// fields don't need schema migrations.
+#[derive(Clone)]
+pub struct WalletStore {
+    pool: AnyPool,
+}
+
+impl WalletStore {
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        sqlx::any::install_default_drivers();
+
+        // SQLite creates the database file but not its directory
+        if let Some(path) = database_url.strip_prefix("sqlite://") {
+            let path = path.split('?').next().unwrap_or_default();
+            if let Some(parent) = Path::new(path).parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+
+        // Every connection to an in-memory SQLite database sees a different database
+        let max_connections = if database_url.contains(":memory:") { 1 } else { 5 };
+        let pool = AnyPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(database_url)
+            .await?;
+
+        sqlx::query("CREATE TABLE IF NOT EXISTS wallets (address TEXT PRIMARY KEY, data TEXT NOT NULL)")
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    pub(crate) fn pool(&self) -> AnyPool {
+        self.pool.clone()
+    }
+
+    pub async fn load_wallets(&self) -> Result<Vec<Wallet>> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT data FROM wallets")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut wallets = rows
+            .into_iter()
+            .map(|(data,)| Ok(serde_json::from_str(&data)?))
+            .collect::<Result<Vec<Wallet>>>()?;
+        wallets.sort_by_key(|wallet| wallet.metadata.created_at);
+        Ok(wallets)
+    }
+
+    pub async fn insert_wallet(&self, wallet: &Wallet) -> Result<()> {
+        sqlx::query("INSERT INTO wallets (address, data) VALUES ($1, $2)")
+            .bind(&wallet.address)
+            .bind(serde_json::to_string(wallet)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Overwrites the stored documents of `wallets` in a single transaction:
+    /// either every wallet is updated or none is.
+    pub async fn update_wallets(&self, wallets: &[Wallet]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for wallet in wallets {
+            sqlx::query("UPDATE wallets SET data = $1 WHERE address = $2")
+                .bind(serde_json::to_string(wallet)?)
+                .bind(&wallet.address)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
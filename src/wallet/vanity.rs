@@ -0,0 +1,298 @@
+use super::ChainType;
+use anyhow::{bail, Result};
+use ed25519_dalek::Keypair;
+use ethers::core::k256::ecdsa::SigningKey;
+use ethers::utils::{public_key_to_address, to_checksum};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use zeroize::Zeroizing;
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Keys tried by a worker between updates of the shared attempt counter.
+const ATTEMPT_BATCH: u64 = 256;
+
+/// What a vanity address must look like.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VanityPattern {
+    chain_type: ChainType,
+    prefix: String,
+    suffix: String,
+    case_sensitive: bool,
+}
+
+impl VanityPattern {
+    /// An Ethereum address starting with `prefix` and ending with `suffix`
+    /// (hex, `0x` optional). `case_sensitive` matches letters against the
+    /// EIP-55 checksum casing, which halves the odds per letter.
+    pub fn ethereum(prefix: &str, suffix: &str, case_sensitive: bool) -> Result<Self> {
+        let prefix = prefix.strip_prefix("0x").unwrap_or(prefix);
+        if let Some(c) = prefix.chars().chain(suffix.chars()).find(|c| !c.is_ascii_hexdigit()) {
+            bail!("{:?} is not a hex digit", c);
+        }
+        if prefix.len() + suffix.len() > 40 {
+            bail!("Vanity pattern is longer than an Ethereum address");
+        }
+
+        let fold = |s: &str| if case_sensitive { s.to_string() } else { s.to_ascii_lowercase() };
+        Ok(Self {
+            chain_type: ChainType::Ethereum,
+            prefix: fold(prefix),
+            suffix: fold(suffix),
+            case_sensitive,
+        })
+    }
+
+    /// A Solana address starting with the base58 `prefix`.
+    pub fn solana(prefix: &str) -> Result<Self> {
+        if let Some(c) = prefix.chars().find(|c| !BASE58_ALPHABET.contains(*c)) {
+            bail!("{:?} is not a base58 character", c);
+        }
+        if prefix.len() > 44 {
+            bail!("Vanity pattern is longer than a Solana address");
+        }
+
+        Ok(Self {
+            chain_type: ChainType::Solana,
+            prefix: prefix.to_string(),
+            suffix: String::new(),
+            case_sensitive: true,
+        })
+    }
+
+    pub fn chain_type(&self) -> ChainType {
+        self.chain_type
+    }
+
+    /// Expected number of keys to try before finding a match. For Solana it
+    /// is approximate, as base58 leading characters are not uniform.
+    pub fn difficulty(&self) -> f64 {
+        match self.chain_type {
+            ChainType::Ethereum => {
+                let pattern = || self.prefix.chars().chain(self.suffix.chars());
+                let letters = if self.case_sensitive {
+                    pattern().filter(|c| c.is_ascii_alphabetic()).count()
+                } else {
+                    0
+                };
+                16f64.powi(pattern().count() as i32) * 2f64.powi(letters as i32)
+            }
+            _ => 58f64.powi(self.prefix.len() as i32),
+        }
+    }
+
+    /// Whether `address` fits the pattern. Ethereum addresses are expected in
+    /// EIP-55 checksum form.
+    pub fn matches(&self, address: &str) -> bool {
+        let address = match self.chain_type {
+            ChainType::Ethereum => address.strip_prefix("0x").unwrap_or(address),
+            _ => address,
+        };
+        if self.case_sensitive {
+            address.starts_with(&self.prefix) && address.ends_with(&self.suffix)
+        } else {
+            let address = address.to_ascii_lowercase();
+            address.starts_with(&self.prefix) && address.ends_with(&self.suffix)
+        }
+    }
+}
+
+/// A key whose address matches a `VanityPattern`, to be stored with
+/// `WalletService::store_vanity_wallet`.
+pub struct VanityKey {
+    pub address: String,
+    pub chain_type: ChainType,
+    pub(crate) private_key: Zeroizing<Vec<u8>>,
+    pub(crate) public_key: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VanityProgress {
+    pub attempts: u64,
+    pub elapsed: Duration,
+    pub attempts_per_sec: f64,
+    /// Expected attempts for the pattern; see `VanityPattern::difficulty`.
+    pub difficulty: f64,
+    /// Chance that a match would have been found by now.
+    pub probability: f64,
+}
+
+impl VanityProgress {
+    /// Time until the search reaches a 50% chance of success at the current
+    /// rate; zero once past it.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.attempts_per_sec <= 0.0 {
+            return None;
+        }
+        let median_attempts = self.difficulty * std::f64::consts::LN_2;
+        let remaining = (median_attempts - self.attempts as f64).max(0.0) / self.attempts_per_sec;
+        Some(Duration::from_secs_f64(remaining))
+    }
+}
+
+/// Shared view of a running search, for reporting progress and cancelling
+/// from elsewhere.
+#[derive(Clone)]
+pub struct VanityHandle {
+    attempts: Arc<AtomicU64>,
+    stop: Arc<AtomicBool>,
+    started: Instant,
+    difficulty: f64,
+}
+
+impl VanityHandle {
+    pub fn progress(&self) -> VanityProgress {
+        let attempts = self.attempts.load(Ordering::Relaxed);
+        let elapsed = self.started.elapsed();
+        VanityProgress {
+            attempts,
+            elapsed,
+            attempts_per_sec: attempts as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            difficulty: self.difficulty,
+            probability: 1.0 - (-(attempts as f64) / self.difficulty).exp(),
+        }
+    }
+
+    /// Stops the workers; `VanitySearch::finish` then fails.
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A vanity address search running on its own OS threads. Dropping it
+/// cancels the search.
+pub struct VanitySearch {
+    handle: VanityHandle,
+    found: mpsc::UnboundedReceiver<VanityKey>,
+}
+
+impl VanitySearch {
+    /// Starts searching with `threads` workers, or one per CPU when `None`.
+    pub fn start(pattern: VanityPattern, threads: Option<usize>) -> Self {
+        let threads = threads
+            .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+            .unwrap_or(1)
+            .max(1);
+        let handle = VanityHandle {
+            attempts: Arc::new(AtomicU64::new(0)),
+            stop: Arc::new(AtomicBool::new(false)),
+            started: Instant::now(),
+            difficulty: pattern.difficulty(),
+        };
+
+        let (sender, found) = mpsc::unbounded_channel();
+        for _ in 0..threads {
+            let (pattern, handle, sender) = (pattern.clone(), handle.clone(), sender.clone());
+            std::thread::spawn(move || search(&pattern, &handle, &sender));
+        }
+
+        Self { handle, found }
+    }
+
+    pub fn handle(&self) -> VanityHandle {
+        self.handle.clone()
+    }
+
+    pub fn progress(&self) -> VanityProgress {
+        self.handle.progress()
+    }
+
+    pub fn cancel(&self) {
+        self.handle.cancel()
+    }
+
+    /// Waits for a matching key.
+    pub async fn finish(mut self) -> Result<VanityKey> {
+        match self.found.recv().await {
+            Some(key) => Ok(key),
+            None => bail!("Vanity search cancelled"),
+        }
+    }
+}
+
+impl Drop for VanitySearch {
+    fn drop(&mut self) {
+        self.handle.cancel();
+    }
+}
+
+/// Worker loop: generates keys until one matches or the search stops.
+fn search(pattern: &VanityPattern, handle: &VanityHandle, found: &mpsc::UnboundedSender<VanityKey>) {
+    let mut rng = rand::thread_rng();
+    while !handle.stop.load(Ordering::Relaxed) {
+        for _ in 0..ATTEMPT_BATCH {
+            let key = match pattern.chain_type {
+                ChainType::Ethereum => {
+                    let signing_key = SigningKey::random(&mut rng);
+                    let address = to_checksum(&public_key_to_address(signing_key.verifying_key()), None);
+                    if !pattern.matches(&address) {
+                        continue;
+                    }
+                    VanityKey {
+                        address,
+                        chain_type: ChainType::Ethereum,
+                        private_key: Zeroizing::new(signing_key.to_bytes().to_vec()),
+                        public_key: signing_key.verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+                    }
+                }
+                _ => {
+                    let keypair = Keypair::generate(&mut rng);
+                    let address = bs58::encode(keypair.public.as_bytes()).into_string();
+                    if !pattern.matches(&address) {
+                        continue;
+                    }
+                    VanityKey {
+                        address,
+                        chain_type: ChainType::Solana,
+                        private_key: Zeroizing::new(keypair.secret.to_bytes().to_vec()),
+                        public_key: keypair.public.to_bytes().to_vec(),
+                    }
+                }
+            };
+
+            // Only the first match is used; the others stop
+            if !handle.stop.swap(true, Ordering::Relaxed) {
+                let _ = found.send(key);
+            }
+            return;
+        }
+        handle.attempts.fetch_add(ATTEMPT_BATCH, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::derive_public_key;
+
+    #[test]
+    fn test_vanity_pattern() {
+        let pattern = VanityPattern::ethereum("0xBeE", "", true).unwrap();
+        assert_eq!(pattern.difficulty(), 4096.0 * 8.0);
+        assert!(pattern.matches("0xBeE1a2aA9a6D4F1b8B3C58C8F5D8A8E9B4D6c2a1"));
+        assert!(!pattern.matches("0xbee1a2aa9a6d4f1b8b3c58c8f5d8a8e9b4d6c2a1"));
+
+        let pattern = VanityPattern::ethereum("", "Ca", false).unwrap();
+        assert_eq!(pattern.difficulty(), 256.0);
+        assert!(pattern.matches("0xbee1a2aa9a6d4f1b8b3c58c8f5d8a8e9b4d6c2CA"));
+
+        assert!(VanityPattern::ethereum("0xcafeg", "", false).is_err());
+        assert!(VanityPattern::solana("Sol").is_ok());
+        assert!(VanityPattern::solana("S0l").is_err());
+        assert_eq!(VanityPattern::solana("ab").unwrap().difficulty(), 3364.0);
+    }
+
+    #[tokio::test]
+    async fn test_vanity_search() {
+        let pattern = VanityPattern::ethereum("0xa", "", false).unwrap();
+        let key = VanitySearch::start(pattern.clone(), Some(2)).finish().await.unwrap();
+        assert!(pattern.matches(&key.address));
+        assert_eq!(derive_public_key(&key.private_key, &ChainType::Ethereum).unwrap(), key.public_key);
+
+        let search = VanitySearch::start(VanityPattern::ethereum(&"0".repeat(40), "", false).unwrap(), Some(1));
+        search.cancel();
+        assert!(search.finish().await.is_err());
+    }
+}